@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{AsmError, Mode};
+
+const PROGRAM_START: usize = 0x200;
+
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Include(Vec<u8>),
+}
+
+struct Line {
+    number: usize,
+    addr: usize,
+    statement: Statement,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    V(u16),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(u16),
+    Value(i64),
+}
+
+/// Splits a comma separated operand list, keeping quoted strings whole.
+fn split_operands(text: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut cur = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                cur.push(c);
+            }
+            ',' if !quoted => res.push(std::mem::take(&mut cur).trim().to_string()),
+            _ => cur.push(c),
+        }
+    }
+    if !cur.trim().is_empty() || !res.is_empty() {
+        res.push(cur.trim().to_string());
+    }
+    res
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn parse_number(token: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(hex) = token.strip_prefix('#').or(token.strip_prefix('$')) {
+        (hex, 16)
+    } else if let Some(hex) = token.strip_prefix("0x").or(token.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(bin) = token.strip_prefix('%').or(token.strip_prefix("0b")) {
+        (bin, 2)
+    } else {
+        (token, 10)
+    };
+    // sprite rows may be drawn with `#` for set bits and `.` for unset ones
+    let digits = if radix == 2 {
+        digits.replace('.', "0").replace('#', "1")
+    } else {
+        digits.to_string()
+    };
+    i64::from_str_radix(&digits, radix).ok()
+}
+
+fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Assembler {
+    mode: Mode,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, String>,
+}
+
+impl Assembler {
+    /// Evaluates `term (('+' | '-') term)*`, where a term is a number, a
+    /// label or a constant.
+    fn eval(&self, expr: &str, depth: usize) -> Result<i64, String> {
+        if depth > 16 {
+            return Err(format!("constant {} refers to itself", expr));
+        }
+
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        let mut terms = vec![];
+        for c in expr.chars().chain(std::iter::once('+')) {
+            match c {
+                '+' | '-' if !term.trim().is_empty() => {
+                    terms.push((sign, std::mem::take(&mut term)));
+                    sign = if c == '-' { -1 } else { 1 };
+                }
+                '-' => sign = -sign,
+                '+' => (),
+                _ => term.push(c),
+            }
+        }
+        if terms.is_empty() {
+            return Err("missing value".to_string());
+        }
+
+        for (sign, term) in terms {
+            let term = term.trim();
+            let value = if let Some(value) = parse_number(term) {
+                value
+            } else if let Some(&addr) = self.labels.get(term) {
+                addr as i64
+            } else if let Some(expr) = self.constants.get(term) {
+                self.eval(expr, depth + 1)?
+            } else if is_identifier(term) {
+                return Err(format!("undefined label or constant {}", term));
+            } else {
+                return Err(format!("invalid value {}", term));
+            };
+            total = value
+                .checked_mul(sign)
+                .and_then(|value| total.checked_add(value))
+                .ok_or_else(|| format!("{} is out of range", expr))?;
+        }
+        Ok(total)
+    }
+
+    fn value(&self, expr: &str, max: i64) -> Result<u16, String> {
+        let value = self.eval(expr, 0)?;
+        // negative bytes and words down to -128 and -32768 are written as two's
+        // complement
+        let value = if (-(max + 1) / 2..0).contains(&value) && matches!(max, 0xFF | 0xFFFF) {
+            value + max + 1
+        } else {
+            value
+        };
+        if !(0..=max).contains(&value) {
+            return Err(format!("{} is out of range (0-{:#X})", expr, max));
+        }
+        Ok(value as u16)
+    }
+
+    fn operand(&self, text: &str) -> Result<Operand, String> {
+        let upper = text.to_ascii_uppercase();
+        Ok(match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            _ if upper.len() == 2
+                && upper.starts_with('V')
+                && upper.as_bytes()[1].is_ascii_hexdigit() =>
+            {
+                Operand::V(u16::from_str_radix(&upper[1..], 16).unwrap())
+            }
+            _ => match upper.strip_prefix("LONG ") {
+                Some(_) => Operand::Long(self.value(text[5..].trim(), 0xFFFF)?),
+                None => Operand::Value(self.eval(text, 0)?),
+            },
+        })
+    }
+
+    fn require_xo_chip(&self, mnemonic: &str) -> Result<(), String> {
+        if self.mode == Mode::XoChip {
+            Ok(())
+        } else {
+            Err(format!("{} needs XO-CHIP mode", mnemonic))
+        }
+    }
+
+    /// Encodes one instruction as its 16-bit words.
+    fn encode(&self, mnemonic: &str, operands: &[String]) -> Result<Vec<u16>, String> {
+        use Operand::*;
+
+        let ops = operands
+            .iter()
+            .map(|op| self.operand(op))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // plain numbers have to fit the field they are encoded in; negative
+        // bytes are written as two's complement
+        let field = |v: i64, min: i64, max: i64, what: &str| {
+            if (min..=max).contains(&v) {
+                Ok((v & max) as u16)
+            } else {
+                Err(format!("{} {} is out of range", what, v))
+            }
+        };
+        let addr = |v: i64| field(v, 0, 0xFFF, "address");
+        let byte = |v: i64| field(v, -0x80, 0xFF, "byte");
+        let nibble = |v: i64| field(v, 0, 0xF, "nibble");
+
+        let word = match (mnemonic, ops.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCD", [Value(n)]) => 0x00C0 | nibble(*n)?,
+            ("SCU", [Value(n)]) => {
+                self.require_xo_chip(mnemonic)?;
+                0x00D0 | nibble(*n)?
+            }
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("SYS", [Value(a)]) => addr(*a)?,
+            ("JP", [Value(a)]) => 0x1000 | addr(*a)?,
+            ("JP", [V(0), Value(a)]) => 0xB000 | addr(*a)?,
+            ("CALL", [Value(a)]) => 0x2000 | addr(*a)?,
+            ("SE", [V(x), Value(b)]) => 0x3000 | x << 8 | byte(*b)?,
+            ("SNE", [V(x), Value(b)]) => 0x4000 | x << 8 | byte(*b)?,
+            ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+            ("SAVE", [V(x), V(y)]) => {
+                self.require_xo_chip(mnemonic)?;
+                0x5002 | x << 8 | y << 4
+            }
+            ("LOAD", [V(x), V(y)]) => {
+                self.require_xo_chip(mnemonic)?;
+                0x5003 | x << 8 | y << 4
+            }
+            ("LD", [V(x), Value(b)]) => 0x6000 | x << 8 | byte(*b)?,
+            ("ADD", [V(x), Value(b)]) => 0x7000 | x << 8 | byte(*b)?,
+            ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+            ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+            ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+            ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+            ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+            ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+            ("SHR", [V(x)]) => 0x8006 | x << 8 | x << 4,
+            ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+            ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+            ("SHL", [V(x)]) => 0x800E | x << 8 | x << 4,
+            ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+            ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+            ("LD", [I, Value(a)]) => 0xA000 | addr(*a)?,
+            ("RND", [V(x), Value(b)]) => 0xC000 | x << 8 | byte(*b)?,
+            ("DRW", [V(x), V(y), Value(n)]) => 0xD000 | x << 8 | y << 4 | nibble(*n)?,
+            ("SKP", [V(x)]) => 0xE09E | x << 8,
+            ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+            ("LD", [I, Long(a)]) => {
+                self.require_xo_chip(mnemonic)?;
+                return Ok(vec![0xF000, *a]);
+            }
+            ("PLANE", [Value(n)]) => {
+                self.require_xo_chip(mnemonic)?;
+                0xF001 | field(*n, 0, 3, "plane")? << 8
+            }
+            ("AUDIO", []) => {
+                self.require_xo_chip(mnemonic)?;
+                0xF002
+            }
+            ("LD", [V(x), Dt]) => 0xF007 | x << 8,
+            ("LD", [V(x), K]) => 0xF00A | x << 8,
+            ("LD", [Dt, V(x)]) => 0xF015 | x << 8,
+            ("LD", [St, V(x)]) => 0xF018 | x << 8,
+            ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+            ("LD", [F, V(x)]) => 0xF029 | x << 8,
+            ("LD", [Hf, V(x)]) => 0xF030 | x << 8,
+            ("LD", [B, V(x)]) => 0xF033 | x << 8,
+            ("PITCH", [V(x)]) => {
+                self.require_xo_chip(mnemonic)?;
+                0xF03A | x << 8
+            }
+            ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+            ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+            ("LD", [R, V(x)]) => 0xF075 | x << 8,
+            ("LD", [V(x), R]) => 0xF085 | x << 8,
+            _ => {
+                return Err(format!(
+                    "invalid instruction {} {}",
+                    mnemonic,
+                    operands.join(", ")
+                ))
+            }
+        };
+        Ok(vec![word])
+    }
+
+    fn data(&self, values: &[String], max: i64) -> Result<Vec<u16>, String> {
+        values.iter().map(|value| self.value(value, max)).collect()
+    }
+}
+
+/// Assembles Cowgod-syntax source (the same syntax `disasm -s cowgod`
+/// emits) into a ROM loaded at 0x200.
+///
+/// Besides the instructions, the source can contain `label:` definitions,
+/// `NAME EQU value` constants, `DB`/`DW` data (with `%` binary literals for
+/// sprites, which may use `#` and `.` for 1 and 0) and `INCLUDE "file"` to
+/// insert a binary file, resolved relative to `base`. XO-CHIP instructions
+/// are only accepted in XO-CHIP mode.
+pub fn assemble(source: &str, mode: Mode, base: &Path) -> Result<Vec<u8>, AsmError> {
+    let mut asm = Assembler {
+        mode,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+    };
+    let mut lines = vec![];
+    let mut addr = PROGRAM_START;
+
+    // first pass: split lines into statements and give every label an address
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: String| AsmError {
+            line: number,
+            message,
+        };
+        let mut text = strip_comment(text).trim();
+
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_identifier(label) {
+                break;
+            }
+            if asm.labels.insert(label.to_string(), addr).is_some() {
+                return Err(error(format!("label {} is already defined", label)));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+
+        if let Some((name, value)) = rest
+            .strip_prefix("EQU ")
+            .or(rest.strip_prefix("equ "))
+            .map(|value| (mnemonic, value.trim()))
+        {
+            if !is_identifier(name)
+                || asm
+                    .constants
+                    .insert(name.to_string(), value.to_string())
+                    .is_some()
+            {
+                return Err(error(format!("invalid or duplicate constant {}", name)));
+            }
+            continue;
+        }
+
+        let operands = split_operands(rest);
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let (statement, size) = match mnemonic.as_str() {
+            "DB" => (Statement::Bytes(operands.clone()), operands.len()),
+            "DW" => (Statement::Words(operands.clone()), operands.len() * 2),
+            "INCLUDE" => {
+                let name = rest.trim_matches('"');
+                let data = fs::read(base.join(name))
+                    .map_err(|err| error(format!("can't include {}: {}", name, err)))?;
+                let size = data.len();
+                (Statement::Include(data), size)
+            }
+            _ => {
+                let long = operands
+                    .iter()
+                    .any(|op| op.to_ascii_uppercase().starts_with("LONG "));
+                let size = if long { 4 } else { 2 };
+                (Statement::Instruction { mnemonic, operands }, size)
+            }
+        };
+
+        // report the line that overflows, not the end of the source
+        let max = mode.memory_size() - PROGRAM_START;
+        if addr + size - PROGRAM_START > max {
+            return Err(error(format!(
+                "program is {} bytes by here but only {} fit in memory",
+                addr + size - PROGRAM_START,
+                max
+            )));
+        }
+
+        lines.push(Line {
+            number,
+            addr,
+            statement,
+        });
+        addr += size;
+    }
+
+    // second pass: encode everything now all labels are known
+    let mut rom = vec![];
+    for line in lines {
+        let error = |message: String| AsmError {
+            line: line.number,
+            message,
+        };
+        debug_assert_eq!(PROGRAM_START + rom.len(), line.addr);
+
+        match &line.statement {
+            Statement::Instruction { mnemonic, operands } => {
+                for word in asm.encode(mnemonic, operands).map_err(error)? {
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+            Statement::Bytes(values) => {
+                for byte in asm.data(values, 0xFF).map_err(error)? {
+                    rom.push(byte as u8);
+                }
+            }
+            Statement::Words(values) => {
+                for word in asm.data(values, 0xFFFF).map_err(error)? {
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+            Statement::Include(data) => rom.extend_from_slice(data),
+        }
+    }
+
+    Ok(rom)
+}
@@ -1,15 +1,25 @@
 #![allow(dead_code)]
 
-use rand::{rngs::ThreadRng, Rng};
-use std::fs::{metadata, File};
+use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
 
 use byteorder::{BigEndian, ByteOrder};
 
-use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+mod builder;
+mod mnemonic;
+mod state;
 
-const FONT: [u8; 80] = [
+pub use builder::CpuBuilder;
+
+use crate::{CpuError, Quirks, Rng, HIRES_HEIGHT, HIRES_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const STACK_SIZE: usize = 16;
+const PROGRAM_START: usize = 0x200;
+
+pub const FONT_ADDR: usize = 0x50;
+
+pub const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -28,25 +38,78 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
-#[derive(PartialEq, Eq)]
+pub const BIG_FONT_ADDR: usize = FONT_ADDR + FONT.len();
+
+pub const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// CHIP-8 with the SUPER-CHIP 1.1 extensions
+    Chip8,
+    /// XO-CHIP: 64 KiB memory, two bitplanes and the extended opcodes
+    XoChip,
+}
+
+impl Mode {
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Mode::Chip8 => 0x1000,
+            Mode::XoChip => 0x10000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A data read or write made by the last instruction (instruction fetches
+/// are not included).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub addr: usize,
+    pub access: Access,
+}
+
+#[derive(Clone, PartialEq, Eq)]
 pub enum State {
     Play,
     Pause,
+    Exit,
 }
 
-#[derive(Debug)]
-struct Instruction {
-    full: u16,
-    op: u8,
-    x: usize,
-    y: usize,
-    n: u8,
-    nn: u8,
-    nnn: u16,
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub full: u16,
+    pub op: u8,
+    pub x: usize,
+    pub y: usize,
+    pub n: u8,
+    pub nn: u8,
+    pub nnn: u16,
 }
 
 impl Instruction {
-    fn from(full: u16) -> Self {
+    pub fn from(full: u16) -> Self {
         Self {
             full,
             op: (full >> 12) as u8,
@@ -59,88 +122,370 @@ impl Instruction {
     }
 }
 
+#[derive(Clone)]
 pub struct Cpu {
-    pub pixels: [bool; (SCREEN_HEIGHT * SCREEN_WIDTH) as usize],
+    /// Row-major framebuffer; only the first `width() * height()` entries
+    /// are in use, laid out with a stride of `width()`. Each pixel holds one
+    /// bit per bitplane, so it doubles as a colour index 0-3.
+    pub pixels: [u8; (HIRES_HEIGHT * HIRES_WIDTH) as usize],
     pub keys: [bool; 16],
-    memory: [u8; 4096],
+    memory: Vec<u8>,
     stack: Vec<usize>,
     v: [u8; 16],
     i: u16,
     pc: usize,
     delay_timer: u8,
     pub sound_timer: u8,
-    rng: ThreadRng,
+    rng: Rng,
     state: State,
+    quirks: Quirks,
+    vblank: bool,
+    hires: bool,
+    rpl: [u8; 16],
+    mode: Mode,
+    planes: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    rom_hash: u64,
+    log_accesses: bool,
+    accesses: Vec<MemoryAccess>,
 }
 
 impl Cpu {
-    pub fn new(rom: &PathBuf) -> Cpu {
+    pub fn new(rom: &PathBuf, quirks: Quirks, mode: Mode) -> Result<Cpu, CpuError> {
+        let mut data = vec![];
+        File::open(rom)?.read_to_end(&mut data)?;
+        Cpu::from_rom(&data, quirks, mode)
+    }
+
+    /// Starts building a `Cpu` for `rom` with chosen starting registers.
+    pub fn builder(rom: &[u8]) -> CpuBuilder<'_> {
+        CpuBuilder::new(rom)
+    }
+
+    /// Like `new`, for a ROM that is already in memory, e.g. one compiled
+    /// from source.
+    pub fn from_rom(rom: &[u8], quirks: Quirks, mode: Mode) -> Result<Cpu, CpuError> {
         let mut res = Cpu {
-            pixels: [false; (SCREEN_HEIGHT * SCREEN_WIDTH) as usize],
+            pixels: [0; (HIRES_HEIGHT * HIRES_WIDTH) as usize],
             keys: [false; 16],
-            memory: [0; 4096],
+            memory: vec![0; mode.memory_size()],
             stack: vec![],
             v: [0; 16],
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
             delay_timer: 0,
             sound_timer: 0,
-            rng: rand::thread_rng(),
+            rng: Rng::new(rand::random()),
             state: State::Play,
+            quirks,
+            vblank: false,
+            hires: false,
+            rpl: [0; 16],
+            mode,
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
+            rom_hash: 0,
+            log_accesses: false,
+            accesses: vec![],
         };
-        res.load_rom(rom);
-        res.memory[0x50..0x50 + FONT.len()].copy_from_slice(&FONT[..]);
-        res
+        res.load_rom(rom)?;
+        res.memory[FONT_ADDR..FONT_ADDR + FONT.len()].copy_from_slice(&FONT[..]);
+        res.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len()].copy_from_slice(&BIG_FONT[..]);
+        Ok(res)
     }
 
-    fn load_rom(&mut self, filename: &PathBuf) {
-        let mut f = File::open(filename).expect("failed to open file");
-        let metadata = metadata(filename).expect("failed to parse metadata");
-        let mut rom = vec![0; metadata.len() as usize];
-        f.read_exact(&mut rom).expect("failed to read");
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), CpuError> {
+        let max = self.memory.len() - self.pc;
+        if rom.len() > max {
+            return Err(CpuError::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
 
-        self.memory[self.pc..(rom.len() + self.pc)].copy_from_slice(&rom[..]);
+        self.memory[self.pc..(rom.len() + self.pc)].copy_from_slice(rom);
+        self.rom_hash = state::fnv1a(rom);
+        Ok(())
     }
 
     pub fn is_paused(&self) -> bool {
         self.state == State::Pause
     }
 
+    /// Set once the program has run 00FD.
+    pub fn has_exited(&self) -> bool {
+        self.state == State::Exit
+    }
+
     pub fn toggle_state(&mut self) {
         self.state = match self.state {
             State::Play => State::Pause,
             State::Pause => State::Play,
+            State::Exit => State::Exit,
         };
     }
 
-    pub fn tick(&mut self) {
-        let instruction = self.decode();
-        self.execute(instruction)
+    pub fn width(&self) -> u32 {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            SCREEN_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        }
+    }
+
+    /// The SUPER-CHIP RPL user flags, kept so a frontend can persist them
+    /// between runs like the HP-48 did.
+    pub fn rpl_flags(&self) -> [u8; 16] {
+        self.rpl
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: [u8; 16]) {
+        self.rpl = flags;
+    }
+
+    /// Runs a single instruction. On error the PC is left pointing at the
+    /// faulting instruction so it can be inspected with `dump_registers`.
+    pub fn tick(&mut self) -> Result<(), CpuError> {
+        self.accesses.clear();
+        let pc = self.pc;
+        let res = self
+            .decode()
+            .and_then(|instruction| self.execute(instruction));
+        if res.is_err() {
+            self.pc = pc;
+        }
+        res
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
     }
 
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Replaces the CXNN generator; `Cpu::new` seeds one from entropy.
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    pub fn rng(&self) -> Rng {
+        self.rng
+    }
+
+    /// The XO-CHIP 1-bit audio pattern, or `None` until the program loads
+    /// one with F002.
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    /// Rate in bits per second the audio pattern should be played back at.
+    pub fn pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Called once per 60Hz frame; this is also the vertical blank that
+    /// DXYN waits for when the display-wait quirk is on.
     pub fn decrement_timers(&mut self) {
         self.sound_timer = self.sound_timer.saturating_sub(1);
         self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.vblank = true;
+        self.rng.interrupt();
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// All of memory, including the fonts below 0x200.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// The XO-CHIP bitplanes selected with FN01, one bit per plane.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// The XO-CHIP pitch register set with FX3A.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// True if the instruction at PC is a DXYN that will wait for the next
+    /// vertical blank before drawing.
+    pub fn is_waiting_for_display(&self) -> bool {
+        self.quirks.display_wait
+            && !self.vblank
+            && matches!(self.current_instruction(), Some(ins) if ins.op == 0xD)
+    }
+
+    pub(crate) fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// A hash of the visible screen, which also differs between lores and
+    /// hires.
+    pub fn screen_hash(&self) -> u64 {
+        state::fnv1a(&self.pixels[..(self.width() * self.height()) as usize])
+    }
+
+    /// Turns on recording of the memory accesses each instruction makes,
+    /// which is off by default as it slows down sprite drawing.
+    pub fn set_access_log(&mut self, enabled: bool) {
+        self.log_accesses = enabled;
+    }
+
+    /// Memory accesses made by the last instruction, if logging is on.
+    pub fn accesses(&self) -> &[MemoryAccess] {
+        &self.accesses
+    }
+
+    /// The instruction at PC, or `None` if PC is outside memory.
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.decode().ok()
+    }
+
+    pub fn dump_registers(&self) -> String {
+        let opcode = match self.check_range(self.pc, 2) {
+            Ok(()) => format!("{:04X}", BigEndian::read_u16(&self.memory[self.pc..])),
+            Err(_) => "????".to_string(),
+        };
+        let mut res = format!(
+            "PC: {:03X}  OP: {}  I: {:03X}  DT: {:02X}  ST: {:02X}\n",
+            self.pc, opcode, self.i, self.delay_timer, self.sound_timer
+        );
+        for (reg, val) in self.v.iter().enumerate() {
+            res += &format!(
+                "V{:X}: {:02X}{}",
+                reg,
+                val,
+                if reg % 8 == 7 { "\n" } else { "  " }
+            );
+        }
+        let stack: Vec<String> = self
+            .stack
+            .iter()
+            .map(|addr| format!("{:03X}", addr))
+            .collect();
+        res += &format!("Stack: [{}]", stack.join(", "));
+        res
+    }
+
+    fn check_range(&self, addr: usize, len: usize) -> Result<(), CpuError> {
+        if addr + len > self.memory.len() {
+            return Err(CpuError::MemoryOutOfBounds {
+                addr: addr.max(self.memory.len()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Data reads go through here so they can be logged.
+    fn read(&mut self, addr: usize) -> u8 {
+        if self.log_accesses {
+            self.accesses.push(MemoryAccess {
+                addr,
+                access: Access::Read,
+            });
+        }
+        self.memory[addr]
+    }
+
+    /// Data writes go through here so they can be logged.
+    fn write(&mut self, addr: usize, val: u8) {
+        if self.log_accesses {
+            self.accesses.push(MemoryAccess {
+                addr,
+                access: Access::Write,
+            });
+        }
+        self.memory[addr] = val;
     }
 
-    fn decode(&self) -> Instruction {
-        Instruction::from(BigEndian::read_u16(&self.memory[self.pc..=self.pc + 1]))
+    fn decode(&self) -> Result<Instruction, CpuError> {
+        self.check_range(self.pc, 2)?;
+        Ok(Instruction::from(BigEndian::read_u16(
+            &self.memory[self.pc..=self.pc + 1],
+        )))
     }
 
-    fn execute(&mut self, ins: Instruction) {
+    fn execute(&mut self, ins: Instruction) -> Result<(), CpuError> {
+        let xo_chip = self.mode == Mode::XoChip;
+        let unknown = CpuError::UnknownOpcode {
+            pc: self.pc,
+            opcode: ins.full,
+        };
         self.pc += 2;
 
         match ins.op {
-            0x0 => match ins.nn {
+            // 0NNN with a nonzero X is a machine code call on the COSMAC VIP,
+            // not one of these
+            0x0 if ins.x == 0 => match ins.nn {
+                // Scroll down n rows
+                0xC0..=0xCF => self.scroll(0, ins.n as i32),
+
+                // Scroll up n rows
+                0xD0..=0xDF if xo_chip => self.scroll(0, -(ins.n as i32)),
+
                 // Clear pixels
                 0xE0 => {
                     self.clear_pixels();
                 }
 
                 // Return
-                0xEE => self.pc = self.stack.pop().unwrap(),
+                0xEE => self.pc = self.stack.pop().ok_or(CpuError::StackUnderflow)?,
+
+                // Scroll right 4 pixels
+                0xFB => self.scroll(4, 0),
+
+                // Scroll left 4 pixels
+                0xFC => self.scroll(-4, 0),
 
-                _ => unimplemented!(),
+                // Exit interpreter
+                0xFD => {
+                    self.pc -= 2;
+                    self.state = State::Exit;
+                }
+
+                // Low resolution (64x32)
+                0xFE => self.set_hires(false),
+
+                // High resolution (128x64)
+                0xFF => self.set_hires(true),
+
+                _ => return Err(unknown),
             },
 
             // PC = NNN
@@ -148,6 +493,9 @@ impl Cpu {
 
             // Call Subroutine NNN
             0x2 => {
+                if self.stack.len() >= STACK_SIZE {
+                    return Err(CpuError::StackOverflow);
+                }
                 self.stack.push(self.pc);
                 self.pc = ins.nnn as usize
             }
@@ -155,21 +503,39 @@ impl Cpu {
             // PC = PC + 2 IF Vx == nn
             0x3 => {
                 if self.v[ins.x] == ins.nn {
-                    self.pc += 2
+                    self.skip()
                 }
             }
 
             // PC = PC + 2 IF Vx != nn
             0x4 => {
                 if self.v[ins.x] != ins.nn {
-                    self.pc += 2
+                    self.skip()
                 }
             }
 
             // PC = PC + 2 IF Vx == Vy
             0x5 if ins.n == 0 => {
                 if self.v[ins.x] == self.v[ins.y] {
-                    self.pc += 2
+                    self.skip()
+                }
+            }
+
+            // memory[i..] = Vx..=Vy
+            0x5 if ins.n == 2 && xo_chip => {
+                let regs = register_range(ins.x, ins.y);
+                self.check_range(self.i as usize, regs.len())?;
+                for (offset, reg) in regs.into_iter().enumerate() {
+                    self.write(self.i as usize + offset, self.v[reg])
+                }
+            }
+
+            // Vx..=Vy = memory[i..]
+            0x5 if ins.n == 3 && xo_chip => {
+                let regs = register_range(ins.x, ins.y);
+                self.check_range(self.i as usize, regs.len())?;
+                for (offset, reg) in regs.into_iter().enumerate() {
+                    self.v[reg] = self.read(self.i as usize + offset)
                 }
             }
 
@@ -185,89 +551,132 @@ impl Cpu {
                 0x0 => self.v[ins.x] = self.v[ins.y],
 
                 // Vx = Vx | Vy
-                0x1 => self.v[ins.x] |= self.v[ins.y],
+                0x1 => {
+                    self.v[ins.x] |= self.v[ins.y];
+                    self.reset_vf();
+                }
 
                 // Vx = Vx & Vy
-                0x2 => self.v[ins.x] &= self.v[ins.y],
+                0x2 => {
+                    self.v[ins.x] &= self.v[ins.y];
+                    self.reset_vf();
+                }
 
                 // Vx = Vx ^ Vy
-                0x3 => self.v[ins.x] ^= self.v[ins.y],
+                0x3 => {
+                    self.v[ins.x] ^= self.v[ins.y];
+                    self.reset_vf();
+                }
 
                 // Vx = Vx + Vy (overflow in VF)
                 0x4 => {
                     let (res, overflow) = self.v[ins.x].overflowing_add(self.v[ins.y]);
-                    self.v[0xF] = overflow as u8;
                     self.v[ins.x] = res;
+                    self.v[0xF] = overflow as u8;
                 }
 
                 // Vx = Vx - Vy (NOT overflow in VF)
                 0x5 => {
                     let (res, overflow) = self.v[ins.x].overflowing_sub(self.v[ins.y]);
-                    self.v[0xF] = !overflow as u8;
                     self.v[ins.x] = res;
+                    self.v[0xF] = !overflow as u8;
                 }
 
-                // Vx = Vx >> 1 (overflow in VF)
+                // Vx = Vy >> 1 (overflow in VF)
                 0x6 => {
-                    self.v[0xF] = if self.v[ins.x] & 0b00000001 > 0 { 1 } else { 0 };
-                    self.v[ins.x] >>= 1;
+                    let src = self.shift_source(ins);
+                    self.v[ins.x] = src >> 1;
+                    self.v[0xF] = src & 0b00000001;
                 }
 
                 // Vx = Vy - Vx (NOT overflow in VF)
                 0x7 => {
                     let (res, overflow) = self.v[ins.y].overflowing_sub(self.v[ins.x]);
-                    self.v[0xF] = !overflow as u8;
                     self.v[ins.x] = res;
+                    self.v[0xF] = !overflow as u8;
                 }
 
-                // Vx = Vx << 1 (overflow in VF)
+                // Vx = Vy << 1 (overflow in VF)
                 0xE => {
-                    self.v[0xF] = if self.v[ins.x] & 0b10000000 > 0 { 1 } else { 0 };
-                    self.v[ins.x] <<= 1;
+                    let src = self.shift_source(ins);
+                    self.v[ins.x] = src << 1;
+                    self.v[0xF] = src >> 7;
                 }
 
-                _ => unimplemented!(),
+                _ => return Err(unknown),
             },
 
             // PC = PC + 2 IF Vx != Vy
             0x9 if ins.n == 0 => {
                 if self.v[ins.x] != self.v[ins.y] {
-                    self.pc += 2
+                    self.skip()
                 }
             }
 
             // I = nnn
             0xA => self.i = ins.nnn,
 
-            // PC = nnn + V0
-            0xB => self.pc = (ins.nnn + self.v[0] as u16) as usize,
+            // PC = nnn + V0 (or xnn + Vx)
+            0xB => {
+                let offset = if self.quirks.jump_with_offset {
+                    self.v[ins.x]
+                } else {
+                    self.v[0]
+                };
+                self.pc = ins.nnn as usize + offset as usize
+            }
 
             // Vx = rand(0-255) & nn
-            0xC => self.v[ins.x] = self.rng.gen_range(0..255u8) & ins.nn,
+            0xC => self.v[ins.x] = self.rng.next_byte(&self.memory) & ins.nn,
 
             // Draw Sprite from I at (Vx, Vy)
             0xD => {
-                self.draw_sprite(ins);
+                if self.quirks.display_wait && !self.vblank {
+                    self.pc -= 2;
+                } else {
+                    self.vblank = false;
+                    self.draw_sprite(ins)?;
+                }
             }
 
             // keypress things :3
             0xE => match ins.nn {
                 // PC = PC + 2 IF keys[Vx] == True
                 0x9E => {
-                    if self.keys[self.v[ins.x] as usize] {
-                        self.pc += 2
+                    if self.keys[(self.v[ins.x] & 0xF) as usize] {
+                        self.skip()
                     }
                 }
                 // PC = PC + 2 IF keys[Vx] == False
                 0xA1 => {
-                    if !self.keys[self.v[ins.x] as usize] {
-                        self.pc += 2
+                    if !self.keys[(self.v[ins.x] & 0xF) as usize] {
+                        self.skip()
                     }
                 }
-                _ => unimplemented!(),
+                _ => return Err(unknown),
             },
 
             0xF => match ins.nn {
+                // I = nnnn (the following word)
+                0x00 if ins.x == 0 && xo_chip => {
+                    self.check_range(self.pc, 2)?;
+                    self.i = BigEndian::read_u16(&self.memory[self.pc..]);
+                    self.pc += 2;
+                }
+
+                // Select bitplanes n
+                0x01 if xo_chip => self.planes = ins.x as u8 & 0b11,
+
+                // audio_pattern = memory[i..i + 16]
+                0x02 if ins.x == 0 && xo_chip => {
+                    self.check_range(self.i as usize, 16)?;
+                    let mut pattern = [0; 16];
+                    for (offset, byte) in pattern.iter_mut().enumerate() {
+                        *byte = self.read(self.i as usize + offset);
+                    }
+                    self.audio_pattern = Some(pattern);
+                }
+
                 // Vx = DT
                 0x07 => self.v[ins.x] = self.delay_timer,
 
@@ -290,80 +699,211 @@ impl Cpu {
                 // ST = Vx
                 0x18 => self.sound_timer = self.v[ins.x],
 
+                // pitch = Vx
+                0x3A if xo_chip => self.pitch = self.v[ins.x],
+
                 // I = I + Vx
-                0x1E => self.i += self.v[ins.x] as u16,
+                0x1E => self.i = self.i.wrapping_add(self.v[ins.x] as u16),
 
                 // I = font[Vx] memory location
-                0x29 => self.i = self.v[ins.x] as u16 * 5 + 0x50,
+                0x29 => self.i = (self.v[ins.x] & 0xF) as u16 * 5 + FONT_ADDR as u16,
+
+                // I = big_font[Vx] memory location
+                0x30 => self.i = (self.v[ins.x] & 0xF) as u16 * 10 + BIG_FONT_ADDR as u16,
 
                 // memory[i..i + 2] = Vx BCD
                 0x33 => {
-                    self.memory[self.i as usize] = self.v[ins.x] / 100;
-                    self.memory[self.i as usize + 1] = self.v[ins.x] % 100 / 10;
-                    self.memory[self.i as usize + 2] = self.v[ins.x] % 10
+                    self.check_range(self.i as usize, 3)?;
+                    self.write(self.i as usize, self.v[ins.x] / 100);
+                    self.write(self.i as usize + 1, self.v[ins.x] % 100 / 10);
+                    self.write(self.i as usize + 2, self.v[ins.x] % 10)
                 }
 
-                // memory[i..i + x] = V0..Vx
+                // memory[i..=i + x] = V0..=Vx
                 0x55 => {
-                    for reg in 0..ins.x {
-                        self.memory[self.i as usize + reg] = self.v[reg]
+                    self.check_range(self.i as usize, ins.x + 1)?;
+                    for reg in 0..=ins.x {
+                        self.write(self.i as usize + reg, self.v[reg])
                     }
+                    self.increment_i(ins);
                 }
 
-                // V0..Vx = memory[i..i + x]
+                // V0..=Vx = memory[i..=i + x]
                 0x65 => {
-                    for reg in 0..ins.x {
-                        self.v[reg] = self.memory[self.i as usize + reg]
+                    self.check_range(self.i as usize, ins.x + 1)?;
+                    for reg in 0..=ins.x {
+                        self.v[reg] = self.read(self.i as usize + reg)
                     }
+                    self.increment_i(ins);
                 }
-                _ => unimplemented!(),
+
+                // rpl[0..=x] = V0..=Vx
+                0x75 => self.rpl[..=ins.x].copy_from_slice(&self.v[..=ins.x]),
+
+                // V0..=Vx = rpl[0..=x]
+                0x85 => self.v[..=ins.x].copy_from_slice(&self.rpl[..=ins.x]),
+                _ => return Err(unknown),
             },
-            _ => unimplemented!(),
+            _ => return Err(unknown),
+        }
+        Ok(())
+    }
+
+    /// Skips the next instruction, which is 4 bytes long if it is an
+    /// XO-CHIP F000 NNNN.
+    fn skip(&mut self) {
+        let long = self.mode == Mode::XoChip
+            && self.check_range(self.pc, 2).is_ok()
+            && BigEndian::read_u16(&self.memory[self.pc..]) == 0xF000;
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+
+    fn shift_source(&self, ins: Instruction) -> u8 {
+        if self.quirks.shift {
+            self.v[ins.x]
+        } else {
+            self.v[ins.y]
+        }
+    }
+
+    fn increment_i(&mut self, ins: Instruction) {
+        if self.quirks.load_store_increment {
+            self.i = self.i.wrapping_add(ins.x as u16 + 1);
         }
     }
 
     fn clear_pixels(&mut self) {
         for pixel in self.pixels.iter_mut() {
-            *pixel = false
+            *pixel &= !self.planes
+        }
+    }
+
+    /// Switches resolution. Unless the quirk clears the screen, the picture
+    /// is kept: each low-res pixel becomes a 2x2 block, and going back to
+    /// low-res keeps the top left pixel of each block.
+    fn set_hires(&mut self, hires: bool) {
+        if self.quirks.resolution_clear {
+            self.hires = hires;
+            self.pixels = [0; (HIRES_HEIGHT * HIRES_WIDTH) as usize];
+            return;
+        }
+        if self.hires == hires {
+            return;
+        }
+
+        let old = self.pixels;
+        let old_width = self.width() as usize;
+        self.hires = hires;
+        let width = self.width() as usize;
+        for y in 0..self.height() as usize {
+            for x in 0..width {
+                let (old_x, old_y) = if hires {
+                    (x / 2, y / 2)
+                } else {
+                    (x * 2, y * 2)
+                };
+                self.pixels[y * width + x] = old[old_y * old_width + old_x];
+            }
         }
     }
 
-    fn draw_sprite(&mut self, ins: Instruction) {
+    /// Shifts the selected bitplanes by (dx, dy) pixels, filling the gap
+    /// with blank pixels.
+    fn scroll(&mut self, dx: i32, dy: i32) {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        let old = self.pixels;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[(src_y * width + src_x) as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !self.planes) | (moved & self.planes);
+            }
+        }
+    }
+
+    fn draw_sprite(&mut self, ins: Instruction) -> Result<(), CpuError> {
+        // DXY0 draws a 16x16 sprite from 32 bytes
+        let (sprite_width, height) = if ins.n == 0 {
+            (16, 16)
+        } else {
+            (8, ins.n as u32)
+        };
+        let row_bytes = sprite_width / 8;
+
+        // each selected bitplane reads its own copy of the sprite, one after the other
+        let planes: Vec<u8> = [0b01, 0b10]
+            .into_iter()
+            .filter(|plane| self.planes & plane != 0)
+            .collect();
+        let plane_bytes = (height * row_bytes) as usize;
+        self.check_range(self.i as usize, plane_bytes * planes.len())?;
+
+        let (width, screen_height) = (self.width(), self.height());
+
         // start coords, wrapped round (modulo screen dimensions)
-        let start_x = self.v[ins.x] & 63;
-        let start_y = self.v[ins.y] & 31;
-        let height = ins.n;
+        let start_x = self.v[ins.x] as u32 % width;
+        let start_y = self.v[ins.y] as u32 % screen_height;
         self.v[0xF] = 0;
 
-        for row in 0..height {
-            let sprite_byte = self.memory[self.i as usize + row as usize];
+        for (index, plane) in planes.into_iter().enumerate() {
+            let base = self.i as usize + index * plane_bytes;
 
-            for col in 0..8u8 {
-                // match current bit in byte
-                let sprite_bit = !matches!(sprite_byte & (0x80 >> col), 0);
+            for row in 0..height {
+                let addr = base + (row * row_bytes) as usize;
+                let sprite_row = if row_bytes == 2 {
+                    (self.read(addr) as u32) << 8 | self.read(addr + 1) as u32
+                } else {
+                    self.read(addr) as u32
+                };
 
-                let cur_row = (start_y + row) as u32;
-                let cur_col = (start_x + col) as u32;
+                for col in 0..sprite_width {
+                    // match current bit in row
+                    let sprite_bit = sprite_row & (1 << (sprite_width - 1 - col)) != 0;
 
-                if cur_row >= SCREEN_HEIGHT || cur_col >= SCREEN_WIDTH {
-                    continue;
-                }
+                    let mut cur_row = start_y + row;
+                    let mut cur_col = start_x + col;
+
+                    if cur_row >= screen_height || cur_col >= width {
+                        if self.quirks.clipping {
+                            continue;
+                        }
+                        cur_row %= screen_height;
+                        cur_col %= width;
+                    }
 
-                let screen_pixel = &mut self.pixels[(cur_row * SCREEN_WIDTH + cur_col) as usize];
+                    let screen_pixel = &mut self.pixels[(cur_row * width + cur_col) as usize];
 
-                // if pixel is on in the sprite
-                if sprite_bit {
-                    match (&sprite_bit, &screen_pixel) {
-                        (true, true) => {
-                            *screen_pixel = false;
+                    // if pixel is on in the sprite
+                    if sprite_bit {
+                        if *screen_pixel & plane != 0 {
                             self.v[0xF] = 1
                         }
-                        (true, false) => *screen_pixel = true,
-                        (false, true) => *screen_pixel = true,
-                        (false, false) => *screen_pixel = false,
+                        *screen_pixel ^= plane;
                     }
                 }
             }
         }
+        Ok(())
+    }
+}
+
+/// Registers Vx..=Vy in order, counting down if x > y.
+fn register_range(x: usize, y: usize) -> Vec<usize> {
+    if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
     }
 }
@@ -0,0 +1,123 @@
+use super::{Cpu, Mode, PROGRAM_START, STACK_SIZE};
+use crate::{CpuError, Quirks, Rng};
+
+/// Builds a `Cpu` from a ROM in memory with chosen starting registers, for
+/// testing single instructions or resuming from a known state.
+///
+/// ```
+/// use chip8::Cpu;
+///
+/// // 8014: V0 += V1
+/// let mut cpu = Cpu::builder(&[0x80, 0x14]).v(0, 0xFF).v(1, 0x02).build().unwrap();
+/// cpu.tick().unwrap();
+/// assert_eq!(cpu.v()[0], 0x01);
+/// assert_eq!(cpu.v()[0xF], 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CpuBuilder<'a> {
+    rom: &'a [u8],
+    quirks: Quirks,
+    mode: Mode,
+    v: [u8; 16],
+    i: u16,
+    pc: usize,
+    stack: Vec<usize>,
+    delay_timer: u8,
+    sound_timer: u8,
+    rng: Option<Rng>,
+}
+
+impl<'a> CpuBuilder<'a> {
+    /// The ROM is loaded at 0x200; everything else starts as it would for
+    /// `Cpu::from_rom` with the default quirks in CHIP-8 mode.
+    pub fn new(rom: &'a [u8]) -> Self {
+        CpuBuilder {
+            rom,
+            quirks: Quirks::default(),
+            mode: Mode::Chip8,
+            v: [0; 16],
+            i: 0,
+            pc: PROGRAM_START,
+            stack: vec![],
+            delay_timer: 0,
+            sound_timer: 0,
+            rng: None,
+        }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets a single register, `reg` being 0-F.
+    pub fn v(mut self, reg: usize, value: u8) -> Self {
+        self.v[reg] = value;
+        self
+    }
+
+    pub fn registers(mut self, v: [u8; 16]) -> Self {
+        self.v = v;
+        self
+    }
+
+    pub fn i(mut self, i: u16) -> Self {
+        self.i = i;
+        self
+    }
+
+    pub fn pc(mut self, pc: usize) -> Self {
+        self.pc = pc;
+        self
+    }
+
+    /// Return addresses, innermost last.
+    pub fn stack(mut self, stack: &[usize]) -> Self {
+        self.stack = stack.to_vec();
+        self
+    }
+
+    pub fn delay_timer(mut self, value: u8) -> Self {
+        self.delay_timer = value;
+        self
+    }
+
+    pub fn sound_timer(mut self, value: u8) -> Self {
+        self.sound_timer = value;
+        self
+    }
+
+    /// Replaces the entropy-seeded CXNN generator.
+    pub fn rng(mut self, rng: Rng) -> Self {
+        self.rng = Some(rng);
+        self
+    }
+
+    /// Fails if the ROM doesn't fit, PC is outside memory or the stack is
+    /// deeper than the 16 levels a program could reach.
+    pub fn build(self) -> Result<Cpu, CpuError> {
+        let mut cpu = Cpu::from_rom(self.rom, self.quirks, self.mode)?;
+        if self.pc >= cpu.memory.len() {
+            return Err(CpuError::MemoryOutOfBounds { addr: self.pc });
+        }
+        if self.stack.len() > STACK_SIZE {
+            return Err(CpuError::StackOverflow);
+        }
+
+        cpu.v = self.v;
+        cpu.i = self.i;
+        cpu.pc = self.pc;
+        cpu.stack = self.stack;
+        cpu.delay_timer = self.delay_timer;
+        cpu.sound_timer = self.sound_timer;
+        if let Some(rng) = self.rng {
+            cpu.rng = rng;
+        }
+        Ok(cpu)
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+
+use super::Instruction;
+
+/// Cowgod-style mnemonics, with SUPER-CHIP and XO-CHIP extensions. Opcodes
+/// that don't decode are shown as a raw data word.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Instruction {
+            full,
+            op,
+            x,
+            y,
+            n,
+            nn,
+            nnn,
+        } = *self;
+
+        match (op, nn, n) {
+            (0x0, 0xE0, _) => write!(f, "CLS"),
+            (0x0, 0xEE, _) => write!(f, "RET"),
+            (0x0, 0xC0..=0xCF, _) => write!(f, "SCD {}", n),
+            (0x0, 0xD0..=0xDF, _) => write!(f, "SCU {}", n),
+            (0x0, 0xFB, _) => write!(f, "SCR"),
+            (0x0, 0xFC, _) => write!(f, "SCL"),
+            (0x0, 0xFD, _) => write!(f, "EXIT"),
+            (0x0, 0xFE, _) => write!(f, "LOW"),
+            (0x0, 0xFF, _) => write!(f, "HIGH"),
+            (0x0, _, _) => write!(f, "SYS #{:03X}", nnn),
+            (0x1, _, _) => write!(f, "JP #{:03X}", nnn),
+            (0x2, _, _) => write!(f, "CALL #{:03X}", nnn),
+            (0x3, _, _) => write!(f, "SE V{:X}, #{:02X}", x, nn),
+            (0x4, _, _) => write!(f, "SNE V{:X}, #{:02X}", x, nn),
+            (0x5, _, 0x0) => write!(f, "SE V{:X}, V{:X}", x, y),
+            (0x5, _, 0x2) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            (0x5, _, 0x3) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            (0x6, _, _) => write!(f, "LD V{:X}, #{:02X}", x, nn),
+            (0x7, _, _) => write!(f, "ADD V{:X}, #{:02X}", x, nn),
+            (0x8, _, 0x0) => write!(f, "LD V{:X}, V{:X}", x, y),
+            (0x8, _, 0x1) => write!(f, "OR V{:X}, V{:X}", x, y),
+            (0x8, _, 0x2) => write!(f, "AND V{:X}, V{:X}", x, y),
+            (0x8, _, 0x3) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            (0x8, _, 0x4) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            (0x8, _, 0x5) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            (0x8, _, 0x6) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            (0x8, _, 0x7) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            (0x8, _, 0xE) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            (0x9, _, 0x0) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            (0xA, _, _) => write!(f, "LD I, #{:03X}", nnn),
+            (0xB, _, _) => write!(f, "JP V0, #{:03X}", nnn),
+            (0xC, _, _) => write!(f, "RND V{:X}, #{:02X}", x, nn),
+            (0xD, _, _) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            (0xE, 0x9E, _) => write!(f, "SKP V{:X}", x),
+            (0xE, 0xA1, _) => write!(f, "SKNP V{:X}", x),
+            (0xF, 0x00, _) if x == 0 => write!(f, "LD I, LONG"),
+            (0xF, 0x01, _) => write!(f, "PLANE {}", x),
+            (0xF, 0x02, _) if x == 0 => write!(f, "AUDIO"),
+            (0xF, 0x07, _) => write!(f, "LD V{:X}, DT", x),
+            (0xF, 0x0A, _) => write!(f, "LD V{:X}, K", x),
+            (0xF, 0x15, _) => write!(f, "LD DT, V{:X}", x),
+            (0xF, 0x18, _) => write!(f, "LD ST, V{:X}", x),
+            (0xF, 0x1E, _) => write!(f, "ADD I, V{:X}", x),
+            (0xF, 0x29, _) => write!(f, "LD F, V{:X}", x),
+            (0xF, 0x30, _) => write!(f, "LD HF, V{:X}", x),
+            (0xF, 0x33, _) => write!(f, "LD B, V{:X}", x),
+            (0xF, 0x3A, _) => write!(f, "PITCH V{:X}", x),
+            (0xF, 0x55, _) => write!(f, "LD [I], V{:X}", x),
+            (0xF, 0x65, _) => write!(f, "LD V{:X}, [I]", x),
+            (0xF, 0x75, _) => write!(f, "LD R, V{:X}", x),
+            (0xF, 0x85, _) => write!(f, "LD V{:X}, R", x),
+            _ => write!(f, "DW #{:04X}", full),
+        }
+    }
+}
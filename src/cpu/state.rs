@@ -0,0 +1,158 @@
+use std::io::{Cursor, Read};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{Cpu, STACK_SIZE};
+use crate::{Rng, RngKind, StateError};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+
+/// FNV-1a, used to tie save states to the ROM they were taken from and
+/// to compare screens when replaying movies.
+pub(super) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Cpu {
+    /// Serializes the full machine state. The layout is a magic number and
+    /// version byte followed by big-endian fields in declaration order.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.write_u64::<BigEndian>(self.rom_hash).unwrap();
+
+        out.write_u32::<BigEndian>(self.memory.len() as u32)
+            .unwrap();
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.v);
+        out.write_u16::<BigEndian>(self.i).unwrap();
+        out.write_u32::<BigEndian>(self.pc as u32).unwrap();
+        out.push(self.stack.len() as u8);
+        for addr in &self.stack {
+            out.write_u32::<BigEndian>(*addr as u32).unwrap();
+        }
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+
+        out.push(self.hires as u8);
+        out.push(self.planes);
+        out.extend_from_slice(&self.pixels);
+        out.extend(self.keys.iter().map(|&key| key as u8));
+        out.push(self.vblank as u8);
+        out.extend_from_slice(&self.rpl);
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or_default());
+        out.push(self.pitch);
+        out.push(self.rng.kind as u8);
+        out.write_u64::<BigEndian>(self.rng.state).unwrap();
+        out
+    }
+
+    /// Restores a state written by `save_state`. Nothing is modified unless
+    /// the whole state is valid for the currently loaded ROM, and every field
+    /// is one the `Cpu` could have been in.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut cur = Cursor::new(data);
+
+        let mut magic = [0; 4];
+        cur.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = cur.read_u8()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if cur.read_u64::<BigEndian>()? != self.rom_hash {
+            return Err(StateError::RomMismatch);
+        }
+
+        let mut res = Cpu {
+            stack: vec![],
+            ..self.clone()
+        };
+
+        if cur.read_u32::<BigEndian>()? as usize != res.memory.len() {
+            return Err(StateError::ModeMismatch);
+        }
+        cur.read_exact(&mut res.memory)?;
+        cur.read_exact(&mut res.v)?;
+        res.i = cur.read_u16::<BigEndian>()?;
+        res.pc = read_addr(&mut cur, res.memory.len(), "program counter")?;
+        let depth = cur.read_u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(StateError::OutOfRange("stack depth"));
+        }
+        for _ in 0..depth {
+            res.stack
+                .push(read_addr(&mut cur, res.memory.len(), "return address")?);
+        }
+        res.delay_timer = cur.read_u8()?;
+        res.sound_timer = cur.read_u8()?;
+
+        res.hires = read_bool(&mut cur, "hires flag")?;
+        res.planes = cur.read_u8()?;
+        if res.planes > 0b11 {
+            return Err(StateError::OutOfRange("plane mask"));
+        }
+        cur.read_exact(&mut res.pixels)?;
+        if res.pixels.iter().any(|&pixel| pixel > 0b11) {
+            return Err(StateError::OutOfRange("pixel"));
+        }
+        for key in res.keys.iter_mut() {
+            *key = read_bool(&mut cur, "key")?;
+        }
+        res.vblank = read_bool(&mut cur, "vblank flag")?;
+        cur.read_exact(&mut res.rpl)?;
+        let has_pattern = read_bool(&mut cur, "audio pattern flag")?;
+        let mut pattern = [0; 16];
+        cur.read_exact(&mut pattern)?;
+        res.audio_pattern = has_pattern.then_some(pattern);
+        res.pitch = cur.read_u8()?;
+        let kind = match cur.read_u8()? {
+            0 => RngKind::Xorshift,
+            1 => RngKind::Cosmac,
+            _ => return Err(StateError::OutOfRange("RNG kind")),
+        };
+        let state = cur.read_u64::<BigEndian>()?;
+        // xorshift never leaves 0, and the COSMAC routine only has a
+        // counter and a total
+        let valid = match kind {
+            RngKind::Xorshift => state != 0,
+            RngKind::Cosmac => state <= 0xFFFF,
+        };
+        if !valid {
+            return Err(StateError::OutOfRange("RNG state"));
+        }
+        res.rng = Rng { kind, state };
+
+        *self = res;
+        Ok(())
+    }
+}
+
+fn read_bool(cur: &mut Cursor<&[u8]>, field: &'static str) -> Result<bool, StateError> {
+    match cur.read_u8()? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(StateError::OutOfRange(field)),
+    }
+}
+
+fn read_addr(
+    cur: &mut Cursor<&[u8]>,
+    memory_size: usize,
+    field: &'static str,
+) -> Result<usize, StateError> {
+    // one past the end is where PC is left after running the last
+    // instruction, so it's fine until the next fetch
+    let addr = cur.read_u32::<BigEndian>()? as usize;
+    if addr > memory_size {
+        return Err(StateError::OutOfRange(field));
+    }
+    Ok(addr)
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeSet;
+
+use crate::{Cpu, Instruction, SourceMap, Watchpoint};
+
+/// Where a step started from, so the debugger knows when it is done.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Frame,
+    Return { depth: usize },
+}
+
+/// Why `after_tick` asked for execution to stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// A step or run-to-return finished.
+    Step,
+    /// A watchpoint fired; the message names the watchpoint and the
+    /// instruction that triggered it.
+    Watch(String),
+}
+
+/// The registers and instruction from just before the last tick, for
+/// register watchpoints and reporting.
+#[derive(Default)]
+struct LastTick {
+    pc: usize,
+    instruction: Option<Instruction>,
+    v: [u8; 16],
+    i: u16,
+}
+
+/// PC breakpoints, watchpoints and stepping on top of a `Cpu`. The
+/// frontend runs the CPU as usual and asks the debugger before and after
+/// each instruction whether to pause.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    step: Option<Step>,
+    resuming: bool,
+    last: LastTick,
+    source_map: SourceMap,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets the panel and watchpoint reports show the source line of the
+    /// current instruction, for programs compiled from source.
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.source_map = source_map;
+    }
+
+    /// Returns whether a breakpoint is now set at `addr`.
+    pub fn toggle_breakpoint(&mut self, addr: usize) -> bool {
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Call before unpausing, so a breakpoint at the current PC doesn't
+    /// immediately stop execution again.
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    /// Runs until the next 60Hz frame starts.
+    pub fn step_frame(&mut self) {
+        self.resume();
+        self.step = Some(Step::Frame);
+    }
+
+    /// Runs until the current subroutine returns.
+    pub fn run_to_return(&mut self, cpu: &Cpu) {
+        self.resume();
+        self.step = Some(Step::Return {
+            depth: cpu.stack().len(),
+        });
+    }
+
+    /// Checked before each instruction; true if a breakpoint was hit.
+    pub fn before_tick(&mut self, cpu: &mut Cpu) -> bool {
+        cpu.set_access_log(self.watchpoints.iter().any(Watchpoint::is_memory));
+        self.last = LastTick {
+            pc: cpu.pc(),
+            instruction: cpu.current_instruction(),
+            v: *cpu.v(),
+            i: cpu.i(),
+        };
+
+        let resuming = std::mem::take(&mut self.resuming);
+        !resuming && self.breakpoints.contains(&cpu.pc())
+    }
+
+    /// Checked after each instruction; says why to stop, if at all.
+    pub fn after_tick(&mut self, cpu: &Cpu) -> Option<Stop> {
+        let last = &self.last;
+        for watchpoint in &self.watchpoints {
+            if let Some(detail) = watchpoint.check(cpu, &last.v, last.i) {
+                let instruction = last
+                    .instruction
+                    .map_or("????".to_string(), |ins| ins.to_string());
+                let mut message = format!(
+                    "Watchpoint {} hit at {:03X} {}: {}",
+                    watchpoint, last.pc, instruction, detail
+                );
+                if let Some(source) = self.source_map.describe(last.pc) {
+                    message += &format!(" ({})", source);
+                }
+                return Some(Stop::Watch(message));
+            }
+        }
+
+        match self.step {
+            Some(Step::Return { depth }) if cpu.stack().len() < depth => {
+                self.step = None;
+                Some(Stop::Step)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checked at the start of each 60Hz frame; true if a step just finished.
+    pub fn on_frame(&mut self) -> bool {
+        if self.step == Some(Step::Frame) {
+            self.step = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drops any step in progress, e.g. when the user pauses by hand.
+    pub fn cancel_step(&mut self) {
+        self.step = None;
+    }
+
+    /// Text for a register/disassembly view of `cpu`, one line per entry.
+    pub fn describe(&self, cpu: &Cpu) -> Vec<String> {
+        let mut lines = vec![];
+
+        let instruction = cpu.current_instruction().map_or("????".to_string(), |ins| {
+            format!("{:04X} {}", ins.full, ins)
+        });
+        lines.push(format!("PC {:03X}  {}", cpu.pc(), instruction));
+        if let Some(source) = self.source_map.describe(cpu.pc()) {
+            // keep it to the width of the panel
+            lines.push(source.chars().take(40).collect());
+        }
+        lines.push(format!(
+            "I  {:03X}  DT {:02X}  ST {:02X}",
+            cpu.i(),
+            cpu.delay_timer(),
+            cpu.sound_timer
+        ));
+        lines.push(String::new());
+        for (row, regs) in cpu.v().chunks(4).enumerate() {
+            let regs: Vec<String> = regs
+                .iter()
+                .enumerate()
+                .map(|(col, val)| format!("V{:X} {:02X}", row * 4 + col, val))
+                .collect();
+            lines.push(regs.join("  "));
+        }
+        lines.push(String::new());
+        lines.push("STACK".to_string());
+        for addr in cpu.stack().iter().rev() {
+            lines.push(format!("  {:03X}", addr));
+        }
+        lines.push(String::new());
+        lines.push("BREAKPOINTS".to_string());
+        for addr in self.breakpoints() {
+            let marker = if addr == cpu.pc() { ">" } else { " " };
+            lines.push(format!("{} {:03X}", marker, addr));
+        }
+        if !self.watchpoints.is_empty() {
+            lines.push(String::new());
+            lines.push("WATCHPOINTS".to_string());
+            for watchpoint in &self.watchpoints {
+                lines.push(format!("  {}", watchpoint));
+            }
+        }
+        lines
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{BTreeMap, HashSet};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::{Instruction, Mode};
+
+const PROGRAM_START: usize = 0x200;
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Cowgod's technical reference mnemonics, e.g. `LD V0, #12`
+    Cowgod,
+    /// Octo assembly, e.g. `v0 := 0x12`
+    Octo,
+}
+
+/// What a synthesised label points at. When an address is reached in more
+/// than one way the highest kind wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Loc,
+    Sub,
+}
+
+struct Labels(BTreeMap<usize, LabelKind>);
+
+impl Labels {
+    fn add(&mut self, addr: usize, kind: LabelKind) {
+        let entry = self.0.entry(addr).or_insert(kind);
+        *entry = (*entry).max(kind);
+    }
+
+    fn name(&self, addr: usize) -> Option<String> {
+        self.0.get(&addr).map(|kind| {
+            let prefix = match kind {
+                LabelKind::Data => "data",
+                LabelKind::Loc => "loc",
+                LabelKind::Sub => "sub",
+            };
+            format!("{}_{:03X}", prefix, addr)
+        })
+    }
+}
+
+/// A ROM loaded at 0x200, with the instruction starts found by following
+/// control flow from the entry point.
+struct Program<'a> {
+    rom: &'a [u8],
+    mode: Mode,
+    code: HashSet<usize>,
+    labels: Labels,
+}
+
+impl<'a> Program<'a> {
+    fn word(&self, addr: usize) -> Option<u16> {
+        let offset = addr.checked_sub(PROGRAM_START)?;
+        self.rom.get(offset..offset + 2).map(BigEndian::read_u16)
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        (PROGRAM_START..PROGRAM_START + self.rom.len()).contains(&addr)
+    }
+
+    /// Size of the instruction at `addr`: 4 bytes for XO-CHIP's F000 NNNN.
+    fn len(&self, addr: usize) -> usize {
+        if self.mode == Mode::XoChip && self.word(addr) == Some(0xF000) {
+            4
+        } else {
+            2
+        }
+    }
+
+    fn trace(&mut self) {
+        let mut pending = vec![PROGRAM_START];
+
+        while let Some(addr) = pending.pop() {
+            if self.code.contains(&addr) {
+                continue;
+            }
+            let Some(full) = self.word(addr) else {
+                continue;
+            };
+            if !is_valid(full, self.mode) {
+                continue;
+            }
+            self.code.insert(addr);
+
+            let ins = Instruction::from(full);
+            let next = addr + self.len(addr);
+            let target = ins.nnn as usize;
+
+            match (ins.op, ins.nn, ins.n) {
+                // return and exit
+                (0x0, 0xEE | 0xFD, _) => {}
+                // jump
+                (0x1, _, _) => {
+                    self.labels.add(target, LabelKind::Loc);
+                    pending.push(target);
+                }
+                // call
+                (0x2, _, _) => {
+                    self.labels.add(target, LabelKind::Sub);
+                    pending.push(target);
+                    pending.push(next);
+                }
+                // skips
+                (0x3 | 0x4, _, _) | (0x5 | 0x9, _, 0x0) | (0xE, _, _) => {
+                    pending.push(next);
+                    pending.push(next + self.len(next));
+                }
+                (0xA, _, _) => {
+                    self.labels.add(target, LabelKind::Data);
+                    pending.push(next);
+                }
+                // computed jump: the target can't be known statically
+                (0xB, _, _) => {}
+                _ => pending.push(next),
+            }
+        }
+
+        // a long I load points at data too
+        for &addr in &self.code {
+            if self.len(addr) == 4 {
+                if let Some(target) = self.word(addr + 2) {
+                    self.labels.add(target as usize, LabelKind::Data);
+                }
+            }
+        }
+    }
+
+    fn operand(&self, addr: usize, syntax: Syntax) -> String {
+        match (self.contains(addr), self.labels.name(addr)) {
+            (true, Some(name)) => name,
+            _ => match syntax {
+                Syntax::Cowgod => format!("#{:03X}", addr),
+                Syntax::Octo => format!("0x{:03X}", addr),
+            },
+        }
+    }
+
+    fn data(&self, bytes: &[u8], syntax: Syntax) -> String {
+        let bytes: Vec<String> = bytes
+            .iter()
+            .map(|byte| match syntax {
+                Syntax::Cowgod => format!("#{:02X}", byte),
+                Syntax::Octo => format!("0x{:02X}", byte),
+            })
+            .collect();
+        match syntax {
+            Syntax::Cowgod => format!("DB {}", bytes.join(", ")),
+            Syntax::Octo => bytes.join(" "),
+        }
+    }
+
+    fn label(&self, addr: usize, syntax: Syntax) -> Option<String> {
+        self.labels.name(addr).map(|name| match syntax {
+            Syntax::Cowgod => format!("{}:", name),
+            Syntax::Octo => format!(": {}", name),
+        })
+    }
+
+    /// Whether the instruction at `addr` can be printed as code, i.e. it
+    /// doesn't straddle a label.
+    fn is_code(&self, addr: usize) -> bool {
+        self.code.contains(&addr)
+            && (addr + 1..addr + self.len(addr)).all(|a| self.labels.name(a).is_none())
+    }
+
+    fn render(&self, syntax: Syntax) -> String {
+        let comment = match syntax {
+            Syntax::Cowgod => ';',
+            Syntax::Octo => '#',
+        };
+        let mut out = vec![];
+        if syntax == Syntax::Octo {
+            out.push(": main".to_string());
+        }
+        let end = PROGRAM_START + self.rom.len();
+        let mut addr = PROGRAM_START;
+
+        while addr < end {
+            if let Some(label) = self.label(addr, syntax) {
+                if addr != PROGRAM_START {
+                    out.push(String::new());
+                }
+                out.push(label);
+            }
+
+            if self.is_code(addr) {
+                let len = self.len(addr);
+                let full = self.word(addr).unwrap();
+                let text = match syntax {
+                    Syntax::Cowgod => self.cowgod(addr, full),
+                    Syntax::Octo => self.octo(addr, full),
+                };
+                out.push(format!(
+                    "    {:<23} {} {:03X}: {:04X}",
+                    text, comment, addr, full
+                ));
+                addr += len;
+            } else {
+                // data runs until the next line of code or label
+                let start = addr;
+                addr += 1;
+                while addr < end
+                    && addr - start < DATA_PER_LINE
+                    && !self.is_code(addr)
+                    && self.labels.name(addr).is_none()
+                {
+                    addr += 1;
+                }
+                let text = self.data(
+                    &self.rom[start - PROGRAM_START..addr - PROGRAM_START],
+                    syntax,
+                );
+                out.push(format!("    {:<23} {} {:03X}", text, comment, start));
+            }
+        }
+
+        out.push(String::new());
+        out.join("\n")
+    }
+
+    fn cowgod(&self, addr: usize, full: u16) -> String {
+        let ins = Instruction::from(full);
+        let target = ins.nnn as usize;
+        match ins.op {
+            0x1 => format!("JP {}", self.operand(target, Syntax::Cowgod)),
+            0x2 => format!("CALL {}", self.operand(target, Syntax::Cowgod)),
+            0xA => format!("LD I, {}", self.operand(target, Syntax::Cowgod)),
+            0xF if self.len(addr) == 4 => {
+                let long = self.word(addr + 2).unwrap() as usize;
+                format!("LD I, LONG {}", self.operand(long, Syntax::Cowgod))
+            }
+            _ => ins.to_string(),
+        }
+    }
+
+    fn octo(&self, addr: usize, full: u16) -> String {
+        let Instruction {
+            op,
+            x,
+            y,
+            n,
+            nn,
+            nnn,
+            ..
+        } = Instruction::from(full);
+        let target = self.operand(nnn as usize, Syntax::Octo);
+
+        match (op, nn, n) {
+            (0x0, 0xE0, _) => "clear".to_string(),
+            (0x0, 0xEE, _) => "return".to_string(),
+            (0x0, 0xC0..=0xCF, _) => format!("scroll-down {}", n),
+            (0x0, 0xD0..=0xDF, _) => format!("scroll-up {}", n),
+            (0x0, 0xFB, _) => "scroll-right".to_string(),
+            (0x0, 0xFC, _) => "scroll-left".to_string(),
+            (0x0, 0xFD, _) => "exit".to_string(),
+            (0x0, 0xFE, _) => "lores".to_string(),
+            (0x0, 0xFF, _) => "hires".to_string(),
+            (0x1, _, _) => format!("jump {}", target),
+            (0x2, _, _) => format!(":call {}", target),
+            // Octo's `if ... then` runs the next instruction when the
+            // condition holds, so skips are written with the test inverted
+            (0x3, _, _) => format!("if v{:x} != 0x{:02X} then", x, nn),
+            (0x4, _, _) => format!("if v{:x} == 0x{:02X} then", x, nn),
+            (0x5, _, 0x0) => format!("if v{:x} != v{:x} then", x, y),
+            (0x5, _, 0x2) => format!("save v{:x} - v{:x}", x, y),
+            (0x5, _, 0x3) => format!("load v{:x} - v{:x}", x, y),
+            (0x6, _, _) => format!("v{:x} := 0x{:02X}", x, nn),
+            (0x7, _, _) => format!("v{:x} += 0x{:02X}", x, nn),
+            (0x8, _, 0x0) => format!("v{:x} := v{:x}", x, y),
+            (0x8, _, 0x1) => format!("v{:x} |= v{:x}", x, y),
+            (0x8, _, 0x2) => format!("v{:x} &= v{:x}", x, y),
+            (0x8, _, 0x3) => format!("v{:x} ^= v{:x}", x, y),
+            (0x8, _, 0x4) => format!("v{:x} += v{:x}", x, y),
+            (0x8, _, 0x5) => format!("v{:x} -= v{:x}", x, y),
+            (0x8, _, 0x6) => format!("v{:x} >>= v{:x}", x, y),
+            (0x8, _, 0x7) => format!("v{:x} =- v{:x}", x, y),
+            (0x8, _, 0xE) => format!("v{:x} <<= v{:x}", x, y),
+            (0x9, _, 0x0) => format!("if v{:x} == v{:x} then", x, y),
+            (0xA, _, _) => format!("i := {}", target),
+            (0xB, _, _) => format!("jump0 {}", target),
+            (0xC, _, _) => format!("v{:x} := random 0x{:02X}", x, nn),
+            (0xD, _, _) => format!("sprite v{:x} v{:x} {}", x, y, n),
+            (0xE, 0x9E, _) => format!("if v{:x} -key then", x),
+            (0xE, 0xA1, _) => format!("if v{:x} key then", x),
+            (0xF, 0x00, _) if self.len(addr) == 4 => {
+                let long = self.word(addr + 2).unwrap() as usize;
+                format!("i := long {}", self.operand(long, Syntax::Octo))
+            }
+            (0xF, 0x01, _) => format!("plane {}", x),
+            (0xF, 0x02, _) => "audio".to_string(),
+            (0xF, 0x07, _) => format!("v{:x} := delay", x),
+            (0xF, 0x0A, _) => format!("v{:x} := key", x),
+            (0xF, 0x15, _) => format!("delay := v{:x}", x),
+            (0xF, 0x18, _) => format!("buzzer := v{:x}", x),
+            (0xF, 0x1E, _) => format!("i += v{:x}", x),
+            (0xF, 0x29, _) => format!("i := hex v{:x}", x),
+            (0xF, 0x30, _) => format!("i := bighex v{:x}", x),
+            (0xF, 0x33, _) => format!("bcd v{:x}", x),
+            (0xF, 0x3A, _) => format!("pitch := v{:x}", x),
+            (0xF, 0x55, _) => format!("save v{:x}", x),
+            (0xF, 0x65, _) => format!("load v{:x}", x),
+            (0xF, 0x75, _) => format!("saveflags v{:x}", x),
+            (0xF, 0x85, _) => format!("loadflags v{:x}", x),
+            _ => self.data(&full.to_be_bytes(), Syntax::Octo),
+        }
+    }
+}
+
+/// Whether `full` is an instruction `Cpu` would execute in `mode`.
+fn is_valid(full: u16, mode: Mode) -> bool {
+    let ins = Instruction::from(full);
+    let xo_chip = mode == Mode::XoChip;
+    match (ins.op, ins.nn, ins.n) {
+        (0x0, 0xE0 | 0xEE | 0xC0..=0xCF | 0xFB..=0xFF, _) => ins.x == 0,
+        (0x0, 0xD0..=0xDF, _) => ins.x == 0 && xo_chip,
+        (0x0, _, _) => false,
+        (0x5, _, 0x0) | (0x9, _, 0x0) => true,
+        (0x5, _, 0x2 | 0x3) => xo_chip,
+        (0x5 | 0x9, _, _) => false,
+        (0x8, _, 0x0..=0x7 | 0xE) => true,
+        (0x8, _, _) => false,
+        (0xE, 0x9E | 0xA1, _) => true,
+        (0xE, _, _) => false,
+        (0xF, 0x00 | 0x02, _) => ins.x == 0 && xo_chip,
+        (0xF, 0x01 | 0x3A, _) => xo_chip,
+        (0xF, 0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x30 | 0x33 | 0x55 | 0x65, _) => true,
+        (0xF, 0x75 | 0x85, _) => true,
+        (0xF, _, _) => false,
+        _ => true,
+    }
+}
+
+/// Disassembles a ROM loaded at 0x200. Code is found by following control
+/// flow from 0x200; everything it doesn't reach is emitted as data. Jump
+/// and call targets and I loads get synthesised labels.
+pub fn disassemble(rom: &[u8], syntax: Syntax, mode: Mode) -> String {
+    let mut program = Program {
+        rom,
+        mode,
+        code: HashSet::new(),
+        labels: Labels(BTreeMap::new()),
+    };
+    program.trace();
+    program.render(syntax)
+}
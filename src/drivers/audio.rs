@@ -1,11 +1,142 @@
-pub struct AudioDriver {}
+use clap::ValueEnum;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::Sdl;
+
+const SAMPLE_RATE: i32 = 44100;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    /// Sample at `phase` in [0, 1), scaled to [-1, 1].
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+/// An XO-CHIP 128-bit audio pattern and the bit rate to play it at.
+struct Pattern {
+    bits: [u8; 16],
+    bit_inc: f32,
+    position: f32,
+}
+
+struct Tone {
+    phase: f32,
+    phase_inc: f32,
+    volume: f32,
+    waveform: Waveform,
+    pattern: Option<Pattern>,
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = match &mut self.pattern {
+                Some(pattern) => {
+                    let bit = pattern.position as usize;
+                    pattern.position = (pattern.position + pattern.bit_inc) % 128.0;
+                    if pattern.bits[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        self.volume
+                    } else {
+                        -self.volume
+                    }
+                }
+                None => {
+                    self.phase = (self.phase + self.phase_inc) % 1.0;
+                    self.volume * self.waveform.sample(self.phase)
+                }
+            };
+        }
+    }
+}
+
+pub struct AudioDriver {
+    device: AudioDevice<Tone>,
+    playing: bool,
+    muted: bool,
+    pattern: Option<([u8; 16], f32)>,
+}
 
 impl AudioDriver {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(sdl_context: &Sdl, freq: f32, volume: f32, waveform: Waveform) -> Self {
+        let subsystem = sdl_context.audio().unwrap();
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = subsystem
+            .open_playback(None, &spec, |spec| Tone {
+                phase: 0.0,
+                phase_inc: freq / spec.freq as f32,
+                volume: volume.clamp(0.0, 1.0),
+                waveform,
+                pattern: None,
+            })
+            .unwrap();
+
+        Self {
+            device,
+            playing: false,
+            muted: false,
+            pattern: None,
+        }
+    }
+
+    /// Switches between the plain tone (`None`) and an XO-CHIP audio
+    /// pattern played at `rate` bits per second.
+    pub fn set_pattern(&mut self, pattern: Option<([u8; 16], f32)>) {
+        if pattern == self.pattern {
+            return;
+        }
+        self.pattern = pattern;
+
+        let sample_rate = self.device.spec().freq as f32;
+        let mut tone = self.device.lock();
+        let position = tone.pattern.as_ref().map_or(0.0, |p| p.position);
+        tone.pattern = pattern.map(|(bits, rate)| Pattern {
+            bits,
+            bit_inc: rate / sample_rate,
+            position,
+        });
+    }
+
+    /// Starts or stops the tone; called every loop with whether the sound
+    /// timer is running.
+    pub fn set_playing(&mut self, playing: bool) {
+        let playing = playing && !self.muted;
+        if playing != self.playing {
+            if playing {
+                self.device.resume()
+            } else {
+                self.device.pause()
+            }
+            self.playing = playing;
+        }
     }
 
-    pub fn beep(&self) {
-        println!("beep")
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        if self.muted {
+            self.set_playing(false);
+        }
     }
 }
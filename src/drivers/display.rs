@@ -3,21 +3,27 @@ use sdl2::rect::Rect;
 use sdl2::render::WindowCanvas;
 use sdl2::Sdl;
 
-use crate::{PIXEL_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use super::text::{draw_text, GLYPH_HEIGHT};
+use crate::{PALETTE, PIXEL_SIZE};
+
+const PANEL_WIDTH: u32 = 320;
+const TEXT_SCALE: u32 = 2;
+const LINE_HEIGHT: u32 = (GLYPH_HEIGHT + 2) * TEXT_SCALE;
+
+const TITLE: &str = "CHIP-8";
 
 pub struct DisplayDriver {
     canvas: WindowCanvas,
+    panel: bool,
 }
 
 impl DisplayDriver {
     pub fn new(sdl_context: &Sdl) -> Self {
         let subsystem = sdl_context.video().unwrap();
         let window = subsystem
-            .window(
-                "CHIP-8",
-                SCREEN_WIDTH * PIXEL_SIZE,
-                SCREEN_HEIGHT * PIXEL_SIZE,
-            )
+            .window(TITLE, SCREEN_WIDTH * PIXEL_SIZE, SCREEN_HEIGHT * PIXEL_SIZE)
             .opengl()
             .build()
             .unwrap();
@@ -28,26 +34,84 @@ impl DisplayDriver {
         canvas.clear();
         canvas.present();
 
-        Self { canvas }
+        Self {
+            canvas,
+            panel: false,
+        }
     }
 
-    pub fn draw(&mut self, pixels: &[bool]) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    /// Shows `status` after the name in the window title, or just the name
+    /// if it's empty.
+    pub fn set_status(&mut self, status: &str) {
+        let title = if status.is_empty() {
+            TITLE.to_string()
+        } else {
+            format!("{} - {}", TITLE, status)
+        };
+        // only fails if the title contains a nul byte
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
+
+    /// Draws a `width` x `height` framebuffer scaled to fill the window,
+    /// so hi-res frames use half the pixel size of lo-res ones. Passing
+    /// `panel` widens the window and shows the lines in a side panel.
+    pub fn draw(&mut self, pixels: &[u8], width: u32, height: u32, panel: Option<&[String]>) {
+        self.show_panel(panel.is_some());
+
+        let pixel_size = SCREEN_WIDTH * PIXEL_SIZE / width;
+
+        self.canvas.set_draw_color(PALETTE[0]);
         self.canvas.clear();
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
 
-        for y in 0..SCREEN_HEIGHT {
-            for x in 0..SCREEN_WIDTH {
-                if pixels[(y * SCREEN_WIDTH + x) as usize] {
+        for y in 0..height {
+            for x in 0..width {
+                let colour = pixels[(y * width + x) as usize] as usize & 0b11;
+                if colour != 0 {
+                    self.canvas.set_draw_color(PALETTE[colour]);
                     let _ = self.canvas.fill_rect(Rect::new(
-                        (x * PIXEL_SIZE) as i32,
-                        (y * PIXEL_SIZE) as i32,
-                        PIXEL_SIZE,
-                        PIXEL_SIZE,
+                        (x * pixel_size) as i32,
+                        (y * pixel_size) as i32,
+                        pixel_size,
+                        pixel_size,
                     ));
                 }
             }
         }
+
+        if let Some(lines) = panel {
+            self.draw_panel(lines);
+        }
         self.canvas.present()
     }
+
+    fn show_panel(&mut self, panel: bool) {
+        if panel != self.panel {
+            let width = SCREEN_WIDTH * PIXEL_SIZE + if panel { PANEL_WIDTH } else { 0 };
+            let _ = self
+                .canvas
+                .window_mut()
+                .set_size(width, SCREEN_HEIGHT * PIXEL_SIZE);
+            self.panel = panel;
+        }
+    }
+
+    fn draw_panel(&mut self, lines: &[String]) {
+        let left = (SCREEN_WIDTH * PIXEL_SIZE) as i32;
+        self.canvas.set_draw_color(Color::RGB(32, 32, 32));
+        let _ = self
+            .canvas
+            .fill_rect(Rect::new(left, 0, PANEL_WIDTH, SCREEN_HEIGHT * PIXEL_SIZE));
+
+        self.canvas.set_draw_color(Color::RGB(200, 200, 200));
+        let max_lines = (SCREEN_HEIGHT * PIXEL_SIZE / LINE_HEIGHT) as usize;
+        for (row, line) in lines.iter().take(max_lines).enumerate() {
+            draw_text(
+                &mut self.canvas,
+                left + 8,
+                4 + (row as u32 * LINE_HEIGHT) as i32,
+                line,
+                TEXT_SCALE,
+            );
+        }
+    }
 }
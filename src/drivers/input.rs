@@ -1,63 +1,122 @@
-use sdl2::event::Event::KeyDown;
-use sdl2::keyboard::{KeyboardState, Keycode, Scancode};
-use sdl2::EventPump;
-use sdl2::Sdl;
+use sdl2::controller::GameController;
+use sdl2::event::Event::{ControllerDeviceAdded, ControllerDeviceRemoved, KeyDown};
+use sdl2::keyboard::{KeyboardState, Mod, Scancode};
+use sdl2::{EventPump, GameControllerSubsystem, Sdl};
 
+use super::keymap::Control;
+use super::Keymap;
 use crate::Event;
 
-const SCANCODES: [Scancode; 16] = [
-    Scancode::Num1,
-    Scancode::Num2,
-    Scancode::Num3,
-    Scancode::Num4,
-    Scancode::Q,
-    Scancode::W,
-    Scancode::E,
-    Scancode::R,
-    Scancode::A,
-    Scancode::S,
-    Scancode::D,
-    Scancode::F,
-    Scancode::Z,
-    Scancode::X,
-    Scancode::C,
-    Scancode::V,
-];
+/// How far a stick or trigger has to move to count as pressed, out of
+/// 32767.
+const DEAD_ZONE: i16 = 16384;
 
 pub struct InputDriver {
     event_pump: EventPump,
+    keymap: Keymap,
+    controller_subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
 }
 
 impl InputDriver {
-    pub fn new(sdl_context: &Sdl) -> Self {
+    pub fn new(sdl_context: &Sdl, keymap: Keymap) -> Self {
+        // controllers already plugged in are reported as added by the first
+        // poll, the same as ones plugged in later
         Self {
             event_pump: sdl_context.event_pump().unwrap(),
+            keymap,
+            controller_subsystem: sdl_context.game_controller().unwrap(),
+            controllers: vec![],
         }
     }
 
+    pub fn is_rewinding(&self) -> bool {
+        KeyboardState::new(&self.event_pump).is_scancode_pressed(self.keymap.hotkeys.rewind)
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        KeyboardState::new(&self.event_pump).is_scancode_pressed(self.keymap.hotkeys.turbo)
+    }
+
     pub fn get_inputs(&mut self, keys: &mut [bool; 16]) -> Option<Event> {
         let keyboardstate = KeyboardState::new(&self.event_pump);
-        for (i, key) in SCANCODES.iter().enumerate() {
+        for (i, key) in self.keymap.keypad.iter().enumerate() {
             keys[i] = keyboardstate.is_scancode_pressed(*key)
         }
+        for controller in &self.controllers {
+            for &(control, key) in &self.keymap.controller {
+                keys[key] |= is_held(controller, control);
+            }
+        }
 
         for event in self.event_pump.poll_iter() {
             match event {
                 KeyDown {
-                    keycode: Some(Keycode::SPACE),
-                    ..
-                } => return Some(Event::Toggle),
-                KeyDown {
-                    keycode: Some(Keycode::ESCAPE),
+                    scancode: Some(scancode),
+                    keymod,
                     ..
-                } => return Some(Event::Exit),
-                KeyDown {
-                    keycode: Some(Keycode::BACKSPACE),
-                    ..
-                } => return Some(Event::Reset),
+                } => {
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    if let Some(event) = hotkey(&self.keymap, scancode, shift) {
+                        return Some(event);
+                    }
+                }
+                ControllerDeviceAdded { which, .. } => {
+                    match self.controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            eprintln!("Controller connected: {}", controller.name());
+                            self.controllers.push(controller);
+                        }
+                        Err(err) => eprintln!("Failed to open controller: {}", err),
+                    }
+                }
+                // unlike when it's added, `which` is the instance ID here
+                ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.retain(|controller| {
+                        let removed = controller.instance_id() == which;
+                        if removed {
+                            eprintln!("Controller disconnected: {}", controller.name());
+                        }
+                        !removed
+                    });
+                }
                 _ => (),
             }
         }
         None
     }
 }
+
+fn is_held(controller: &GameController, control: Control) -> bool {
+    match control {
+        Control::Button(button) => controller.button(button),
+        Control::Axis(axis, true) => controller.axis(axis) >= DEAD_ZONE,
+        Control::Axis(axis, false) => controller.axis(axis) <= -DEAD_ZONE,
+    }
+}
+
+fn hotkey(keymap: &Keymap, scancode: Scancode, shift: bool) -> Option<Event> {
+    let hotkeys = &keymap.hotkeys;
+    let event = match scancode {
+        key if key == hotkeys.pause => Event::Toggle,
+        key if key == hotkeys.exit => Event::Exit,
+        key if key == hotkeys.reset => Event::Reset,
+        key if key == hotkeys.mute => Event::Mute,
+        key if key == hotkeys.debugger => Event::Debugger,
+        key if key == hotkeys.breakpoint => Event::Breakpoint,
+        key if key == hotkeys.slow_motion => Event::SlowMotion,
+        key if key == hotkeys.step_instruction => Event::StepInstruction,
+        key if key == hotkeys.step_frame && shift => Event::RunToReturn,
+        key if key == hotkeys.step_frame => Event::StepFrame,
+        // Shift+slot key saves to the slot, the key alone loads it
+        key => {
+            let slot = hotkeys.slots.iter().position(|&slot| slot == key)? as u8 + 1;
+            if shift {
+                Event::SaveState(slot)
+            } else {
+                Event::LoadState(slot)
+            }
+        }
+    };
+    Some(event)
+}
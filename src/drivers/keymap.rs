@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::{Keycode, Scancode};
+use serde::Deserialize;
+
+/// The keypad used when nothing else is configured: the 4x4 block under
+/// 1-4, by position, so it's the same whatever the keyboard layout.
+const KEYPAD: [Scancode; 16] = [
+    Scancode::Num1,
+    Scancode::Num2,
+    Scancode::Num3,
+    Scancode::Num4,
+    Scancode::Q,
+    Scancode::W,
+    Scancode::E,
+    Scancode::R,
+    Scancode::A,
+    Scancode::S,
+    Scancode::D,
+    Scancode::F,
+    Scancode::Z,
+    Scancode::X,
+    Scancode::C,
+    Scancode::V,
+];
+
+/// Controller bindings used when nothing else is configured: the d-pad and
+/// left stick on 2/4/6/8, which most games use as directions, and A on 5.
+const CONTROLLER: [(Control, usize); 9] = [
+    (Control::Button(Button::DPadUp), 0x2),
+    (Control::Button(Button::DPadDown), 0x8),
+    (Control::Button(Button::DPadLeft), 0x4),
+    (Control::Button(Button::DPadRight), 0x6),
+    (Control::Axis(Axis::LeftY, false), 0x2),
+    (Control::Axis(Axis::LeftY, true), 0x8),
+    (Control::Axis(Axis::LeftX, false), 0x4),
+    (Control::Axis(Axis::LeftX, true), 0x6),
+    (Control::Button(Button::A), 0x5),
+];
+
+/// A controller input that can hold a CHIP-8 key down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Button(Button),
+    /// An axis pushed past the dead zone, towards its positive end if
+    /// `true`.
+    Axis(Axis, bool),
+}
+
+/// Keypads that put the CHIP-8 keys on the keys labelled 1-4, Q-R, A-F and
+/// Z-V on other layouts, as many ROMs' instructions name those keys. The
+/// default `KEYPAD` is the same block by position, which is what the
+/// `qwerty` preset gives; the others are scattered on their own layouts but
+/// match what is printed on the keys. Scancodes are positions on a US
+/// keyboard, so these don't depend on the layout the system is set to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Preset {
+    Qwerty,
+    Azerty,
+    Dvorak,
+}
+
+impl Preset {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "qwerty" => Some(Self::Qwerty),
+            "azerty" => Some(Self::Azerty),
+            "dvorak" => Some(Self::Dvorak),
+            _ => None,
+        }
+    }
+
+    fn keypad(self) -> [Scancode; 16] {
+        use Scancode::*;
+        match self {
+            Self::Qwerty => KEYPAD,
+            Self::Azerty => [Num1, Num2, Num3, Num4, A, Z, E, R, Q, S, D, F, W, X, C, V],
+            Self::Dvorak => [
+                Num1, Num2, Num3, Num4, X, Comma, D, O, A, Semicolon, H, Y, Slash, B, I, Period,
+            ],
+        }
+    }
+}
+
+/// Emulator controls. The step frame key runs to the end of the current
+/// subroutine with Shift held, and the slot keys save with Shift held.
+pub struct Hotkeys {
+    pub pause: Scancode,
+    pub exit: Scancode,
+    pub reset: Scancode,
+    pub mute: Scancode,
+    pub debugger: Scancode,
+    pub breakpoint: Scancode,
+    pub slow_motion: Scancode,
+    pub step_instruction: Scancode,
+    pub step_frame: Scancode,
+    pub rewind: Scancode,
+    pub turbo: Scancode,
+    pub slots: [Scancode; 9],
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self {
+            pause: by_name(Keycode::SPACE, Scancode::Space),
+            exit: by_name(Keycode::ESCAPE, Scancode::Escape),
+            reset: by_name(Keycode::BACKSPACE, Scancode::Backspace),
+            mute: by_name(Keycode::M, Scancode::M),
+            debugger: by_name(Keycode::TAB, Scancode::Tab),
+            breakpoint: by_name(Keycode::B, Scancode::B),
+            slow_motion: by_name(Keycode::MINUS, Scancode::Minus),
+            step_instruction: Scancode::F10,
+            step_frame: Scancode::F11,
+            rewind: Scancode::Grave,
+            turbo: Scancode::Equals,
+            slots: [
+                Scancode::F1,
+                Scancode::F2,
+                Scancode::F3,
+                Scancode::F4,
+                Scancode::F5,
+                Scancode::F6,
+                Scancode::F7,
+                Scancode::F8,
+                Scancode::F9,
+            ],
+        }
+    }
+}
+
+/// Where each CHIP-8 key and emulator control is on the keyboard.
+pub struct Keymap {
+    pub keypad: [Scancode; 16],
+    pub hotkeys: Hotkeys,
+    /// Controller inputs and the CHIP-8 key each one holds down.
+    pub controller: Vec<(Control, usize)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            keypad: KEYPAD,
+            hotkeys: Hotkeys::default(),
+            controller: CONTROLLER.to_vec(),
+        }
+    }
+}
+
+/// A keymap file. Any key left out keeps its binding from the layer below.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Replaces the whole keypad before `keys` is applied.
+    preset: Option<Preset>,
+    /// CHIP-8 keys by hex digit.
+    keys: BTreeMap<String, String>,
+    hotkeys: HotkeyNames,
+    /// Controller inputs by hex digit, replacing the key's old bindings.
+    controller: BTreeMap<String, Controls>,
+}
+
+/// One controller input, or a list of them (empty to unbind the key).
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Controls {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct HotkeyNames {
+    pause: Option<String>,
+    exit: Option<String>,
+    reset: Option<String>,
+    mute: Option<String>,
+    debugger: Option<String>,
+    breakpoint: Option<String>,
+    slow_motion: Option<String>,
+    step_instruction: Option<String>,
+    step_frame: Option<String>,
+    rewind: Option<String>,
+    turbo: Option<String>,
+    slots: Option<Vec<String>>,
+}
+
+impl Keymap {
+    /// Builds the keymap from the defaults, then the global keymap file,
+    /// then the ROM's own (`<rom>.keymap.toml`), then `keymap` from the
+    /// command line, which is a preset name or the path to another file.
+    /// Each layer only changes the keys it mentions.
+    ///
+    /// Key names are SDL's (`A`, `Space`, `Left Shift`, `F1`, ...) and are
+    /// looked up in the current keyboard layout, so SDL's video subsystem
+    /// has to be running.
+    pub fn load(rom: &Path, keymap: Option<&str>) -> anyhow::Result<Self> {
+        let mut map = Self::default();
+
+        if let Some(path) = global_path().filter(|path| path.exists()) {
+            map.apply_file(&path)?;
+        }
+        let rom_keymap = rom.with_extension("keymap.toml");
+        if rom_keymap.exists() {
+            map.apply_file(&rom_keymap)?;
+        }
+        match keymap.map(|name| (name, Preset::from_name(name))) {
+            Some((_, Some(preset))) => map.keypad = preset.keypad(),
+            Some((path, None)) => map.apply_file(Path::new(path))?,
+            None => (),
+        }
+        Ok(map)
+    }
+
+    fn apply_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read {}: {}", path.display(), err))?;
+        toml::from_str(&text)
+            .map_err(anyhow::Error::from)
+            .and_then(|config| self.apply(&config))
+            .map_err(|err| anyhow!("{}: {}", path.display(), err))
+    }
+
+    fn apply(&mut self, config: &Config) -> anyhow::Result<()> {
+        if let Some(preset) = config.preset {
+            self.keypad = preset.keypad();
+        }
+        for (digit, name) in &config.keys {
+            self.keypad[chip8_key(digit)?] = scancode(name)?;
+        }
+        for (digit, controls) in &config.controller {
+            let key = chip8_key(digit)?;
+            let names = match controls {
+                Controls::One(name) => std::slice::from_ref(name),
+                Controls::Many(names) => names,
+            };
+            self.controller.retain(|&(_, bound)| bound != key);
+            for name in names {
+                self.controller.push((control(name)?, key));
+            }
+        }
+
+        let names = &config.hotkeys;
+        let hotkeys = &mut self.hotkeys;
+        for (name, key) in [
+            (&names.pause, &mut hotkeys.pause),
+            (&names.exit, &mut hotkeys.exit),
+            (&names.reset, &mut hotkeys.reset),
+            (&names.mute, &mut hotkeys.mute),
+            (&names.debugger, &mut hotkeys.debugger),
+            (&names.breakpoint, &mut hotkeys.breakpoint),
+            (&names.slow_motion, &mut hotkeys.slow_motion),
+            (&names.step_instruction, &mut hotkeys.step_instruction),
+            (&names.step_frame, &mut hotkeys.step_frame),
+            (&names.rewind, &mut hotkeys.rewind),
+            (&names.turbo, &mut hotkeys.turbo),
+        ] {
+            if let Some(name) = name {
+                *key = scancode(name)?;
+            }
+        }
+        if let Some(slots) = &names.slots {
+            if slots.len() > hotkeys.slots.len() {
+                bail!("only {} save state slots", hotkeys.slots.len());
+            }
+            for (slot, name) in hotkeys.slots.iter_mut().zip(slots) {
+                *slot = scancode(name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn chip8_key(digit: &str) -> anyhow::Result<usize> {
+    match usize::from_str_radix(digit, 16) {
+        Ok(key) if digit.len() == 1 => Ok(key),
+        _ => bail!("invalid CHIP-8 key {:?}, expected 0-F", digit),
+    }
+}
+
+/// A controller input by SDL's name for it (`a`, `dpup`, `leftshoulder`,
+/// ...), or an axis name (`leftx`, `righty`, `lefttrigger`, ...) followed
+/// by the direction, `+` or `-`. Triggers only go in the `+` direction, so
+/// it can be left off.
+fn control(name: &str) -> anyhow::Result<Control> {
+    if let Some(button) = Button::from_string(name) {
+        return Ok(Control::Button(button));
+    }
+    let (axis, positive) = match name.strip_suffix('-') {
+        Some(axis) => (axis, false),
+        None => (name.strip_suffix('+').unwrap_or(name), true),
+    };
+    Axis::from_string(axis)
+        .map(|axis| Control::Axis(axis, positive))
+        .ok_or_else(|| anyhow!("unknown controller input {:?}", name))
+}
+
+/// The key labelled `name` in the current layout.
+fn scancode(name: &str) -> anyhow::Result<Scancode> {
+    let keycode = Keycode::from_name(name).ok_or_else(|| anyhow!("unknown key {:?}", name))?;
+    Scancode::from_keycode(keycode)
+        .ok_or_else(|| anyhow!("no {:?} key in the current keyboard layout", name))
+}
+
+/// The key labelled `keycode`, or `fallback` if the layout has no such key.
+fn by_name(keycode: Keycode, fallback: Scancode) -> Scancode {
+    Scancode::from_keycode(keycode).unwrap_or(fallback)
+}
+
+/// `chip8/keymap.toml` in the user's config directory.
+fn global_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(env::var_os("APPDATA")?),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("chip8").join("keymap.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(toml: &str) -> [Scancode; 16] {
+        let config: Config = toml::from_str(toml).unwrap();
+        config.preset.unwrap().keypad()
+    }
+
+    #[test]
+    fn qwerty() {
+        assert_eq!(preset(r#"preset = "qwerty""#), KEYPAD);
+        assert_eq!(Preset::from_name("QWERTY"), Some(Preset::Qwerty));
+    }
+
+    #[test]
+    fn azerty() {
+        let keypad = preset(r#"preset = "azerty""#);
+        assert_eq!(keypad[..4], KEYPAD[..4]);
+        // Q and A, and W and Z, swap places
+        assert_eq!(keypad[0x4], Scancode::A);
+        assert_eq!(keypad[0x5], Scancode::Z);
+        assert_eq!(keypad[0x8], Scancode::Q);
+        assert_eq!(keypad[0xC], Scancode::W);
+        assert_eq!(Preset::from_name("azerty"), Some(Preset::Azerty));
+    }
+
+    #[test]
+    fn dvorak() {
+        let keypad = preset(r#"preset = "dvorak""#);
+        assert_eq!(
+            keypad[4..],
+            [
+                Scancode::X,
+                Scancode::Comma,
+                Scancode::D,
+                Scancode::O,
+                Scancode::A,
+                Scancode::Semicolon,
+                Scancode::H,
+                Scancode::Y,
+                Scancode::Slash,
+                Scancode::B,
+                Scancode::I,
+                Scancode::Period,
+            ]
+        );
+        assert_eq!(Preset::from_name("Dvorak"), Some(Preset::Dvorak));
+    }
+
+    #[test]
+    fn rejects_unknown_presets() {
+        assert!(toml::from_str::<Config>(r#"preset = "colemak""#).is_err());
+        assert_eq!(Preset::from_name("colemak"), None);
+    }
+}
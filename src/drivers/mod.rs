@@ -1,9 +1,12 @@
 mod audio;
 mod display;
 mod input;
+mod keymap;
+mod text;
 mod timing;
 
-pub use self::audio::AudioDriver;
+pub use self::audio::{AudioDriver, Waveform};
 pub use self::display::DisplayDriver;
 pub use self::input::InputDriver;
-pub use self::timing::{check_timers, Timer};
+pub use self::keymap::Keymap;
+pub use self::timing::Scheduler;
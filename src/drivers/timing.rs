@@ -1,39 +1,60 @@
+use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::DoTick;
+/// Frames run back to back to catch up after a stall; any further behind
+/// than this are dropped.
+const MAX_CATCH_UP: u32 = 4;
 
-#[derive(Clone, Copy)]
-pub struct Timer {
-    freq: u128,
-    exec: DoTick,
-    last: Instant,
+/// Paces the main loop at a fixed frame rate. Frames are due at fixed
+/// intervals from the start, so lateness doesn't build up, and the loop
+/// sleeps until the next one is due rather than polling.
+pub struct Scheduler {
+    base: Duration,
+    period: Duration,
+    next: Instant,
 }
 
-impl Timer {
-    pub fn new(hz: u32, exec: DoTick) -> Timer {
-        Timer {
-            freq: 1_000_000 / hz as u128,
-            exec,
-            last: Instant::now(),
+impl Scheduler {
+    pub fn new(fps: u32) -> Self {
+        let period = Duration::from_secs(1) / fps;
+        Self {
+            base: period,
+            period,
+            next: Instant::now() + period,
         }
     }
 
-    fn try_tick(&mut self) -> bool {
-        if self.last.elapsed().as_micros() >= self.freq {
-            self.last = Instant::now();
-            true
-        } else {
-            false
-        }
+    /// Runs `speed` times faster than the frame rate given to `new`, or as
+    /// fast as possible for `None`. Timing restarts from now, so changing
+    /// speed doesn't cause a burst of catch-up frames.
+    pub fn set_speed(&mut self, speed: Option<f64>) {
+        self.period = match speed {
+            Some(speed) => self.base.div_f64(speed),
+            None => Duration::ZERO,
+        };
+        self.next = Instant::now() + self.period;
     }
-}
 
-pub fn check_timers(timers: &mut [Timer]) -> Vec<DoTick> {
-    let mut res = vec![];
-    for timer in timers {
-        if timer.try_tick() {
-            res.push(timer.exec)
+    /// Sleeps until the next frame is due, then returns how many frames to
+    /// run: 1 on time, more if the loop fell behind, up to `MAX_CATCH_UP`.
+    pub fn wait(&mut self) -> u32 {
+        let now = Instant::now();
+        if now < self.next {
+            thread::sleep(self.next - now);
+        }
+
+        if self.period.is_zero() {
+            return 1;
+        }
+
+        let late = Instant::now().saturating_duration_since(self.next);
+        let frames = (late.as_nanos() / self.period.as_nanos()) as u32 + 1;
+        if frames > MAX_CATCH_UP {
+            self.next = Instant::now() + self.period;
+            MAX_CATCH_UP
+        } else {
+            self.next += self.period * frames;
+            frames
         }
     }
-    res
 }
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum CpuError {
+    UnknownOpcode { pc: usize, opcode: u16 },
+    StackUnderflow,
+    StackOverflow,
+    MemoryOutOfBounds { addr: usize },
+    RomTooLarge { size: usize, max: usize },
+    Io(io::Error),
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, pc)
+            }
+            CpuError::StackUnderflow => write!(f, "return with an empty stack"),
+            CpuError::StackOverflow => write!(f, "call with a full stack"),
+            CpuError::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:04X}", addr)
+            }
+            CpuError::RomTooLarge { size, max } => {
+                write!(f, "rom is {} bytes but only {} fit in memory", size, max)
+            }
+            CpuError::Io(err) => write!(f, "failed to read rom: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CpuError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CpuError {
+    fn from(err: io::Error) -> Self {
+        CpuError::Io(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    RomMismatch,
+    ModeMismatch,
+    Truncated,
+    /// A field holds a value the `Cpu` can't be in, e.g. a stack deeper
+    /// than 16; the string names the field.
+    OutOfRange(&'static str),
+    Io(io::Error),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::RomMismatch => write!(f, "save state is for a different rom"),
+            StateError::ModeMismatch => write!(f, "save state is for a different mode"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::OutOfRange(field) => write!(f, "save state has an invalid {}", field),
+            StateError::Io(err) => write!(f, "failed to access save state: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StateError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StateError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => StateError::Truncated,
+            _ => StateError::Io(err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    RomMismatch,
+    Truncated,
+    /// A setting holds a value no recording could have, e.g. an unknown
+    /// mode; the string names the setting.
+    OutOfRange(&'static str),
+    /// The screen after `frame` frames didn't match the recording.
+    Desync {
+        frame: usize,
+        expected: u64,
+        actual: u64,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "unsupported movie version {}", version)
+            }
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different rom"),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::OutOfRange(field) => write!(f, "movie has an invalid {}", field),
+            MovieError::Desync {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "replay desynced at frame {}: screen hash {:016X}, expected {:016X}",
+                frame, actual, expected
+            ),
+            MovieError::Io(err) => write!(f, "failed to access movie: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MovieError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => MovieError::Truncated,
+            _ => MovieError::Io(err),
+        }
+    }
+}
+
+/// An assembly error, tagged with the 1-based source line it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
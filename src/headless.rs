@@ -0,0 +1,200 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use chip8::{Cpu, CpuError, Debugger, FrameClock, Movie, SourceMap, Stop, Tracer};
+
+use crate::{load_movie, load_program, report_breakpoint, report_fault, tick, Args, PALETTE};
+
+/// Keys held from a given instruction onwards, in order.
+pub type KeyScript = Vec<(u64, [bool; 16])>;
+
+/// Characters for each combination of the two bitplanes.
+const ASCII: [char; 4] = ['.', '#', 'o', '@'];
+
+/// Parses `INSTRUCTION:KEYS` entries separated by whitespace or commas,
+/// where `KEYS` are the hex keys held from that instruction on. The
+/// argument can also name a file containing the entries.
+pub fn parse_key_script(s: &str) -> Result<KeyScript, String> {
+    let text = match fs::read_to_string(s) {
+        Ok(text) => text,
+        Err(_) => s.to_string(),
+    };
+
+    let mut script = KeyScript::new();
+    for entry in text.split(|c: char| c.is_whitespace() || c == ',') {
+        if entry.is_empty() {
+            continue;
+        }
+        let (at, held) = entry
+            .split_once(':')
+            .ok_or_else(|| format!("invalid key entry {}: expected INSTRUCTION:KEYS", entry))?;
+        let at = at
+            .parse()
+            .map_err(|err| format!("invalid key entry {}: {}", entry, err))?;
+        let mut keys = [false; 16];
+        for key in held.chars() {
+            let key = key
+                .to_digit(16)
+                .ok_or_else(|| format!("invalid key {} in {}", key, entry))?;
+            keys[key as usize] = true;
+        }
+        script.push((at, keys));
+    }
+    script.sort_by_key(|(at, _)| *at);
+    Ok(script)
+}
+
+/// Runs the ROM on a virtual clock: the timers count down once every
+/// frame's worth of instructions, as in the window, so a run only depends
+/// on its arguments. With `--replay` the movie's frames are run instead.
+pub fn run(args: &Args, filename: &Path) -> anyhow::Result<()> {
+    let program = load_program(filename, args.mode())?;
+    let movie = args.replay.as_deref().map(load_movie).transpose()?;
+    let mut cpu = match &movie {
+        Some(movie) => movie.start(&program.rom)?,
+        None => {
+            let mut cpu = Cpu::from_rom(&program.rom, args.quirks.into(), args.mode())?;
+            // unlike the window, default to a fixed seed so runs are repeatable
+            cpu.set_rng(args.rng(args.seed.unwrap_or(0)));
+            cpu
+        }
+    };
+
+    let mut runner = Runner {
+        debugger: args.debugger(&program),
+        tracer: args.tracer()?,
+        source_map: &program.source_map,
+        clock: FrameClock::new(args.timing()),
+        cycles: 0,
+    };
+    let res = match &movie {
+        Some(movie) => replay(movie, &mut cpu, &mut runner),
+        None => play_script(args, &mut cpu, &mut runner),
+    };
+    eprintln!("Stopped after {} instructions", runner.cycles);
+
+    match &args.dump {
+        Some(path) if path.extension() == Some("png".as_ref()) => write_png(&cpu, path)?,
+        Some(path) => fs::write(path, pbm(&cpu))?,
+        None => print!("{}", ascii(&cpu)),
+    }
+    println!("{}", cpu.dump_registers());
+
+    // a fault or desync fails the run, for CI
+    res
+}
+
+/// Steps the CPU under the debugger and tracer, counting instructions.
+struct Runner<'a> {
+    debugger: Debugger,
+    tracer: Option<Tracer<BufWriter<File>>>,
+    source_map: &'a SourceMap,
+    clock: FrameClock,
+    cycles: u64,
+}
+
+impl Runner<'_> {
+    /// Runs one instruction, returning false if a breakpoint or watchpoint
+    /// stopped the run.
+    fn step(&mut self, cpu: &mut Cpu) -> Result<bool, CpuError> {
+        if self.debugger.before_tick(cpu) {
+            report_breakpoint(cpu, self.source_map);
+            return Ok(false);
+        }
+        self.clock.charge(cpu);
+        if let Err(err) = tick(cpu, &mut self.tracer) {
+            report_fault(cpu, &err, self.source_map);
+            return Err(err);
+        }
+        self.cycles += 1;
+        if let Some(Stop::Watch(message)) = self.debugger.after_tick(cpu) {
+            eprintln!("{}", message);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+fn play_script(args: &Args, cpu: &mut Cpu, runner: &mut Runner) -> anyhow::Result<()> {
+    let mut script = args.keys.iter().flatten().peekable();
+
+    while runner.cycles < args.cycles && !cpu.has_exited() {
+        let cycle = runner.cycles;
+        while let Some((_, keys)) = script.next_if(|(at, _)| *at <= cycle) {
+            cpu.keys = *keys;
+        }
+        if runner.clock.is_done() {
+            cpu.decrement_timers();
+            runner.clock.next_frame();
+        }
+
+        if !runner.step(cpu)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs each frame's instructions with its keys held, then counts the
+/// timers down and checks the screen against the recording.
+fn replay(movie: &Movie, cpu: &mut Cpu, runner: &mut Runner) -> anyhow::Result<()> {
+    for (index, frame) in movie.frames().iter().enumerate() {
+        cpu.keys = frame.keys;
+        for _ in 0..frame.cycles {
+            if cpu.has_exited() || !runner.step(cpu)? {
+                return Ok(());
+            }
+        }
+        cpu.decrement_timers();
+        movie.check(index + 1, cpu)?;
+    }
+    eprintln!("Replayed {} frames", movie.frames().len());
+    Ok(())
+}
+
+fn colours(cpu: &Cpu) -> impl Iterator<Item = usize> + '_ {
+    let len = (cpu.width() * cpu.height()) as usize;
+    cpu.pixels[..len].iter().map(|pixel| *pixel as usize & 0b11)
+}
+
+fn ascii(cpu: &Cpu) -> String {
+    let mut text = String::new();
+    for (index, colour) in colours(cpu).enumerate() {
+        text.push(ASCII[colour]);
+        if (index + 1) % cpu.width() as usize == 0 {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// A plain (text) PBM, any lit pixel being black.
+fn pbm(cpu: &Cpu) -> String {
+    let mut text = format!("P1\n{} {}\n", cpu.width(), cpu.height());
+    for (index, colour) in colours(cpu).enumerate() {
+        text.push(if colour != 0 { '1' } else { '0' });
+        let end = (index + 1) % cpu.width() as usize == 0;
+        text.push(if end { '\n' } else { ' ' });
+    }
+    text
+}
+
+fn write_png(cpu: &Cpu, path: &Path) -> anyhow::Result<()> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        cpu.width(),
+        cpu.height(),
+    );
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        PALETTE
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect::<Vec<_>>(),
+    );
+    let data: Vec<u8> = colours(cpu).map(|colour| colour as u8).collect();
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
@@ -0,0 +1,35 @@
+mod asm;
+mod cpu;
+mod debugger;
+mod disasm;
+mod error;
+mod movie;
+mod octo;
+mod quirks;
+mod rewind;
+mod rng;
+mod timing;
+mod trace;
+mod watch;
+
+pub use asm::assemble;
+pub use cpu::{
+    Access, Cpu, CpuBuilder, Instruction, MemoryAccess, Mode, State, BIG_FONT, BIG_FONT_ADDR, FONT,
+    FONT_ADDR,
+};
+pub use debugger::{Debugger, Stop};
+pub use disasm::{disassemble, Syntax};
+pub use error::{AsmError, CpuError, MovieError, StateError};
+pub use movie::{Frame, Movie};
+pub use octo::{compile, Program, SourceMap};
+pub use quirks::Quirks;
+pub use rewind::RewindBuffer;
+pub use rng::{Rng, RngKind};
+pub use timing::{vip_cycles, FrameClock, Timing, VIP_FRAME_CYCLES, VIP_INTERRUPT_CYCLES};
+pub use trace::Tracer;
+pub use watch::{Register, Watchpoint};
+
+pub const SCREEN_WIDTH: u32 = 64;
+pub const SCREEN_HEIGHT: u32 = 32;
+pub const HIRES_WIDTH: u32 = 128;
+pub const HIRES_HEIGHT: u32 = 64;
@@ -1,84 +1,796 @@
-#![allow(unused_imports)]
-
-mod cpu;
+#[cfg(feature = "sdl")]
 mod drivers;
+mod headless;
 
-use std::{path::PathBuf, time::Instant};
+#[cfg(feature = "sdl")]
+use std::fmt;
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
-use clap::Parser;
-use cpu::{Cpu, State};
-use drivers::{check_timers, AudioDriver, DisplayDriver, InputDriver, Timer};
+use chip8::{
+    assemble, compile, disassemble, Cpu, CpuError, Debugger, Mode, Movie, Program, Quirks, Rng,
+    SourceMap, Syntax, Timing, Tracer, Watchpoint,
+};
+#[cfg(feature = "sdl")]
+use chip8::{Frame, FrameClock, RewindBuffer, StateError, Stop};
+use clap::{Parser, Subcommand, ValueEnum};
+#[cfg(feature = "sdl")]
+use drivers::{AudioDriver, DisplayDriver, InputDriver, Keymap, Scheduler, Waveform};
 
-const SCREEN_WIDTH: u32 = 64;
-const SCREEN_HEIGHT: u32 = 32;
+#[cfg(feature = "sdl")]
 const PIXEL_SIZE: u32 = 10;
 
+/// Colours for each combination of the two bitplanes.
+const PALETTE: [(u8, u8, u8); 4] = [(0, 0, 0), (255, 255, 255), (255, 102, 0), (102, 34, 0)];
+
+#[cfg(feature = "sdl")]
 #[derive(PartialEq, Eq)]
 enum Event {
     Toggle,
     Reset,
+    Mute,
+    SaveState(u8),
+    LoadState(u8),
+    Debugger,
+    Breakpoint,
+    SlowMotion,
+    StepInstruction,
+    StepFrame,
+    RunToReturn,
     Exit,
 }
 
+/// How fast the window runs compared to 60 frames a second.
+#[cfg(feature = "sdl")]
 #[derive(PartialEq, Eq, Clone, Copy)]
-enum DoTick {
-    Cpu,
-    Display,
-    SoundDelay,
+enum Speed {
+    Normal,
+    /// N times faster, or uncapped for 0
+    Turbo(u32),
+    /// N times slower
+    Slow(u32),
+}
+
+#[cfg(feature = "sdl")]
+impl Speed {
+    /// Multiple of the normal frame rate, `None` being uncapped.
+    fn factor(self) -> Option<f64> {
+        match self {
+            Speed::Normal => Some(1.0),
+            Speed::Turbo(0) => None,
+            Speed::Turbo(n) => Some(n as f64),
+            Speed::Slow(n) => Some(1.0 / n as f64),
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Speed::Normal => Ok(()),
+            Speed::Turbo(0) => write!(f, "turbo"),
+            Speed::Turbo(n) => write!(f, "{}x", n),
+            Speed::Slow(n) => write!(f, "1/{}x", n),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum QuirkProfile {
+    /// Original COSMAC VIP interpreter
+    Vip,
+    /// CHIP-48 on the HP-48 calculators
+    Chip48,
+    /// SUPER-CHIP 1.1
+    Schip,
+    /// Octo and most modern interpreters
+    Modern,
+}
+
+impl From<QuirkProfile> for Quirks {
+    fn from(profile: QuirkProfile) -> Self {
+        match profile {
+            QuirkProfile::Vip => Quirks::COSMAC_VIP,
+            QuirkProfile::Chip48 => Quirks::CHIP_48,
+            QuirkProfile::Schip => Quirks::SUPER_CHIP,
+            QuirkProfile::Modern => Quirks::MODERN,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SyntaxArg {
+    /// Cowgod's technical reference mnemonics
+    Cowgod,
+    /// Octo assembly
+    Octo,
+}
+
+impl From<SyntaxArg> for Syntax {
+    fn from(syntax: SyntaxArg) -> Self {
+        match syntax {
+            SyntaxArg::Cowgod => Syntax::Cowgod,
+            SyntaxArg::Octo => Syntax::Octo,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Disassemble a ROM into labelled assembly
+    Disasm {
+        /// ROM file
+        filename: PathBuf,
+
+        /// Assembly syntax to emit
+        #[arg(short = 's', long, value_enum, default_value = "octo")]
+        syntax: SyntaxArg,
+
+        /// Decode XO-CHIP instructions
+        #[arg(short = 'x', long)]
+        xo_chip: bool,
+
+        /// Output file (default: stdout)
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+    },
+    /// Assemble Cowgod-syntax source into a ROM
+    Asm {
+        /// Source file
+        filename: PathBuf,
+
+        /// Allow XO-CHIP instructions
+        #[arg(short = 'x', long)]
+        xo_chip: bool,
+
+        /// Output file (default: the source with a .ch8 extension)
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+    },
 }
 
 #[derive(Parser)]
-#[command(name = "CHIP8_RS", version = "1.0", about)]
+#[command(
+    name = "CHIP8_RS",
+    version = "1.0",
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// ROM file
-    filename: PathBuf,
+    #[arg(required = true)]
+    filename: Option<PathBuf>,
 
-    /// CPU frequency (default: 1000Hz)
+    /// CPU frequency, run as `FREQ / 60` instructions per frame (default:
+    /// 1000Hz)
     #[arg(short = 'f', long)]
     freq: Option<u32>,
+
+    /// Instructions per 60Hz frame, instead of setting a frequency
+    #[arg(long, conflicts_with = "freq")]
+    ipf: Option<u32>,
+
+    /// Run as many instructions per frame as the COSMAC VIP would have,
+    /// going by each one's cycle cost
+    #[arg(long, conflicts_with_all = ["freq", "ipf"])]
+    vip_timing: bool,
+
+    /// Quirk profile for ambiguous instructions
+    #[arg(short = 'q', long, value_enum, default_value = "vip")]
+    quirks: QuirkProfile,
+
+    /// Run in XO-CHIP mode (64 KiB memory, bitplanes, extended opcodes)
+    #[arg(short = 'x', long)]
+    xo_chip: bool,
+
+    #[cfg(feature = "sdl")]
+    /// Tone frequency in Hz
+    #[arg(long, default_value_t = 440.0)]
+    tone: f32,
+
+    #[cfg(feature = "sdl")]
+    /// Volume between 0.0 and 1.0
+    #[arg(long, default_value_t = 0.25)]
+    volume: f32,
+
+    #[cfg(feature = "sdl")]
+    /// Tone waveform
+    #[arg(long, value_enum, default_value = "square")]
+    waveform: Waveform,
+
+    #[cfg(feature = "sdl")]
+    /// Frames of history kept for rewinding (0 disables rewind)
+    #[arg(long, default_value_t = 600)]
+    rewind_depth: usize,
+
+    #[cfg(feature = "sdl")]
+    /// Keyboard preset (qwerty, azerty, dvorak) or keymap file, applied over
+    /// the global and per-ROM keymap files
+    #[arg(long, value_name = "PRESET|FILE")]
+    keymap: Option<String>,
+
+    #[cfg(feature = "sdl")]
+    /// Speed-up while the turbo key is held (0 runs as fast as possible)
+    #[arg(long, default_value_t = 4)]
+    turbo: u32,
+
+    #[cfg(feature = "sdl")]
+    /// Slow-down while slow motion is on
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    slow_motion: u32,
+
+    /// Seed for the CXNN random number generator (default: random)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Approximate the COSMAC VIP's random number routine (the same
+    /// structure, but not the same numbers as a real VIP)
+    #[arg(long)]
+    cosmac_rng: bool,
+
+    /// Set a breakpoint at a hex address (can be repeated)
+    #[arg(short = 'b', long = "break", value_parser = parse_addr)]
+    breakpoints: Vec<usize>,
+
+    /// Halt on a memory access (ADDR[-ADDR][:r|:w|:rw]) or register change
+    /// (REG[=VALUE]), e.g. `300-30F:w` or `VF=1` (can be repeated)
+    #[arg(short = 'w', long = "watch")]
+    watchpoints: Vec<Watchpoint>,
+
+    #[cfg(feature = "sdl")]
+    /// Start paused with the debugger panel open
+    #[arg(short = 'd', long)]
+    debug: bool,
+
+    /// Log every executed instruction to a file
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Only trace instructions in a hex address range (START-END)
+    #[arg(long, value_parser = parse_range, requires = "trace")]
+    trace_range: Option<RangeInclusive<usize>>,
+
+    /// Stop tracing after this many bytes
+    #[arg(long, default_value_t = 64 * 1024 * 1024, requires = "trace")]
+    trace_limit: u64,
+
+    /// Run without a window for a number of instructions, then print the
+    /// screen and registers
+    #[arg(long)]
+    headless: bool,
+
+    /// Instructions to run in headless mode (a replay runs all its frames)
+    #[arg(long, default_value_t = 10_000, requires = "headless")]
+    cycles: u64,
+
+    /// Keys to hold in headless mode, as INSTRUCTION:KEYS entries (or a file
+    /// of them), e.g. `0:5 300: 600:AF` holds 5, then nothing, then A and F
+    #[arg(long, value_parser = headless::parse_key_script, requires = "headless")]
+    keys: Option<headless::KeyScript>,
+
+    /// Write the final screen to a .png or .pbm file instead of printing it
+    #[arg(long, requires = "headless")]
+    dump: Option<PathBuf>,
+
+    #[cfg(feature = "sdl")]
+    /// Record the keys held each frame to a movie file
+    #[arg(long, conflicts_with_all = ["replay", "headless"])]
+    record: Option<PathBuf>,
+
+    /// Replay a movie recorded with --record, stopping if it desyncs
+    #[arg(long, conflicts_with = "keys")]
+    replay: Option<PathBuf>,
+}
+
+fn parse_addr(s: &str) -> Result<usize, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16).map_err(|err| format!("invalid address {}: {}", s, err))
+}
+
+fn parse_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("invalid range {}: expected START-END", s))?;
+    Ok(parse_addr(start)?..=parse_addr(end)?)
+}
+
+impl Args {
+    fn rng(&self, seed: u64) -> Rng {
+        if self.cosmac_rng {
+            Rng::cosmac(seed)
+        } else {
+            Rng::new(seed)
+        }
+    }
+
+    fn timing(&self) -> Timing {
+        if self.vip_timing {
+            return Timing::CosmacVip;
+        }
+        let ipf = match self.ipf {
+            Some(ipf) => ipf,
+            None => (self.freq.unwrap_or(1000) + 30) / 60,
+        };
+        Timing::Instructions(ipf.max(1))
+    }
+
+    fn mode(&self) -> Mode {
+        if self.xo_chip {
+            Mode::XoChip
+        } else {
+            Mode::Chip8
+        }
+    }
+
+    fn debugger(&self, program: &Program) -> Debugger {
+        let mut debugger = Debugger::new();
+        debugger.set_source_map(program.source_map.clone());
+        for addr in program.breakpoints.iter().chain(&self.breakpoints) {
+            debugger.toggle_breakpoint(*addr);
+        }
+        for watchpoint in &self.watchpoints {
+            debugger.add_watchpoint(watchpoint.clone());
+        }
+        debugger
+    }
+
+    fn tracer(&self) -> anyhow::Result<Option<Tracer<BufWriter<File>>>> {
+        let Some(path) = &self.trace else {
+            return Ok(None);
+        };
+        let mut tracer = Tracer::new(BufWriter::new(File::create(path)?));
+        if let Some(range) = &self.trace_range {
+            tracer.set_range(range.clone());
+        }
+        tracer.set_limit(self.trace_limit);
+        Ok(Some(tracer))
+    }
+}
+
+fn report_fault(cpu: &Cpu, err: &CpuError, source_map: &SourceMap) {
+    eprintln!("CPU fault: {}", err);
+    if let Some(source) = source_map.describe(cpu.pc()) {
+        eprintln!("at {}", source);
+    }
+    eprintln!("{}", cpu.dump_registers());
 }
 
-fn main() {
+fn report_breakpoint(cpu: &Cpu, source_map: &SourceMap) {
+    match source_map.describe(cpu.pc()) {
+        Some(source) => eprintln!("Breakpoint hit at {:03X} ({})", cpu.pc(), source),
+        None => eprintln!("Breakpoint hit at {:03X}", cpu.pc()),
+    }
+}
+
+/// Runs one instruction, logging it if tracing is on. A failed write stops
+/// tracing rather than the emulator.
+fn tick(cpu: &mut Cpu, tracer: &mut Option<Tracer<BufWriter<File>>>) -> Result<(), CpuError> {
+    if let Some(tracer) = tracer {
+        tracer.before_tick(cpu);
+    }
+    cpu.tick()?;
+    if let Some(Err(err)) = tracer.as_mut().map(|tracer| tracer.after_tick(cpu)) {
+        eprintln!("Tracing stopped: {}", err);
+        *tracer = None;
+    }
+    Ok(())
+}
+
+/// Reads a ROM, compiling it first if it's Octo source (`.8o`).
+fn load_program(filename: &Path, mode: Mode) -> anyhow::Result<Program> {
+    if filename.extension() == Some("8o".as_ref()) {
+        let source = fs::read_to_string(filename)?;
+        compile(&source, mode).map_err(|err| anyhow::anyhow!("{}: {}", filename.display(), err))
+    } else {
+        Ok(Program {
+            rom: fs::read(filename)?,
+            ..Program::default()
+        })
+    }
+}
+
+fn load_movie(path: &Path) -> anyhow::Result<Movie> {
+    let data = fs::read(path)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path.display(), err))?;
+    Ok(Movie::load(&data)?)
+}
+
+#[cfg(feature = "sdl")]
+/// Save states live next to the ROM as `<rom>.ss<slot>`.
+fn state_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("ss{}", slot))
+}
+
+#[cfg(feature = "sdl")]
+/// SUPER-CHIP RPL flags are kept in a `.rpl` file next to the ROM.
+fn load_rpl_flags(rom: &Path) -> [u8; 16] {
+    let mut flags = [0; 16];
+    if let Ok(saved) = fs::read(rom.with_extension("rpl")) {
+        let len = saved.len().min(flags.len());
+        flags[..len].copy_from_slice(&saved[..len]);
+    }
+    flags
+}
+
+#[cfg(feature = "sdl")]
+/// Flags that are all zero remove the file rather than write one, so ROMs that
+/// never set them don't get a `.rpl` file and ones that clear them don't get
+/// the old flags back.
+fn save_rpl_flags(rom: &Path, flags: [u8; 16]) -> anyhow::Result<()> {
+    let path = rom.with_extension("rpl");
+    if flags != [0; 16] {
+        fs::write(path, flags)?;
+    } else if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn disasm(
+    filename: &Path,
+    syntax: Syntax,
+    mode: Mode,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let rom = fs::read(filename)?;
+    let text = disassemble(&rom, syntax, mode);
+    match output {
+        Some(output) => fs::write(output, text)?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+fn asm(filename: &Path, mode: Mode, output: Option<&Path>) -> anyhow::Result<()> {
+    let source = fs::read_to_string(filename)?;
+    let base = filename.parent().unwrap_or(Path::new("."));
+    let rom = assemble(&source, mode, base)
+        .map_err(|err| anyhow::anyhow!("{}: {}", filename.display(), err))?;
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => filename.with_extension("ch8"),
+    };
+    fs::write(&output, &rom)?;
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    if let Some(Command::Disasm {
+        filename,
+        syntax,
+        xo_chip,
+        output,
+    }) = &args.command
+    {
+        let mode = if *xo_chip { Mode::XoChip } else { Mode::Chip8 };
+        return disasm(filename, (*syntax).into(), mode, output.as_deref());
+    }
+
+    if let Some(Command::Asm {
+        filename,
+        xo_chip,
+        output,
+    }) = &args.command
+    {
+        let mode = if *xo_chip { Mode::XoChip } else { Mode::Chip8 };
+        return asm(filename, mode, output.as_deref());
+    }
+
+    // clap requires a ROM when there's no subcommand
+    let filename = args.filename.clone().unwrap();
+
+    if args.headless {
+        return headless::run(&args, &filename);
+    }
+    run(&args, &filename)
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run(_args: &Args, _filename: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("built without the sdl feature, only --headless is available")
+}
+
+#[cfg(feature = "sdl")]
+fn run(args: &Args, filename: &Path) -> anyhow::Result<()> {
     let sdl_context = sdl2::init().unwrap();
 
-    let audio_driver = AudioDriver::new();
+    let mut audio_driver = AudioDriver::new(&sdl_context, args.tone, args.volume, args.waveform);
     let mut display_driver = DisplayDriver::new(&sdl_context);
-    let mut input_driver = InputDriver::new(&sdl_context);
+    // key names are looked up in the layout, which needs the video subsystem
+    let keymap = Keymap::load(filename, args.keymap.as_deref())?;
+    let mut input_driver = InputDriver::new(&sdl_context, keymap);
 
-    let mut timers: Vec<Timer> = vec![
-        Timer::new(args.freq.unwrap_or(1000), DoTick::Cpu),
-        Timer::new(60, DoTick::Display),
-        Timer::new(60, DoTick::SoundDelay),
-    ];
+    let mut scheduler = Scheduler::new(60);
+    let mut speed = Speed::Normal;
+    let mut slow_motion = false;
+    let mut clock = FrameClock::new(args.timing());
 
-    let mut cpu = Cpu::new(&args.filename);
+    // resets reuse the seed so they replay identically
+    let seed = args.seed.unwrap_or_else(rand::random);
+    eprintln!("RNG seed: {}", seed);
+
+    let program = load_program(filename, args.mode())?;
+    let mut replay = args.replay.as_deref().map(load_movie).transpose()?;
+    let mut cpu = match &replay {
+        Some(movie) => movie.start(&program.rom)?,
+        None => {
+            let mut cpu = Cpu::from_rom(&program.rom, args.quirks.into(), args.mode())?;
+            cpu.set_rng(args.rng(seed));
+            cpu.set_rpl_flags(load_rpl_flags(filename));
+            cpu
+        }
+    };
+
+    let mut keys = [false; 16];
+    let mut recording = args.record.as_ref().map(|_| Movie::new(&cpu, seed));
+    let mut frame_cycles = 0;
+    let mut replay_frame = 0;
+
+    let mut rewind = RewindBuffer::new(args.rewind_depth);
+
+    let mut debugger = args.debugger(&program);
+    let mut tracer = args.tracer()?;
+
+    let mut show_debugger = args.debug;
+    if args.debug {
+        cpu.toggle_state();
+    }
 
     'mainloop: loop {
-        match input_driver.get_inputs(&mut cpu.keys) {
-            Some(Event::Toggle) => cpu.toggle_state(),
-            Some(Event::Reset) => cpu = Cpu::new(&args.filename),
+        let frames = scheduler.wait();
+
+        let event = input_driver.get_inputs(&mut keys);
+        // while recording, keys only change between frames, as they do when
+        // the frames are replayed
+        if replay.is_none() && (recording.is_none() || frame_cycles == 0) {
+            cpu.keys = keys;
+        }
+
+        // anything that jumps the CPU to another state ends a movie
+        if matches!(event, Some(Event::Reset | Event::LoadState(_))) || input_driver.is_rewinding()
+        {
+            end_movie(args, &mut recording, &mut replay);
+        }
+
+        match event {
+            Some(Event::Toggle) => {
+                if cpu.is_paused() {
+                    debugger.resume();
+                } else {
+                    debugger.cancel_step();
+                }
+                cpu.toggle_state()
+            }
+            Some(Event::Reset) => {
+                match Cpu::from_rom(&program.rom, args.quirks.into(), args.mode()) {
+                    Ok(mut new_cpu) => {
+                        new_cpu.set_rng(args.rng(seed));
+                        new_cpu.set_rpl_flags(cpu.rpl_flags());
+                        cpu = new_cpu;
+                        rewind.clear();
+                    }
+                    Err(err) => eprintln!("Reset failed: {}", err),
+                }
+            }
+            Some(Event::Mute) => audio_driver.toggle_mute(),
+            Some(Event::SaveState(slot)) => {
+                let path = state_path(filename, slot);
+                match fs::write(&path, cpu.save_state()) {
+                    Ok(()) => eprintln!("Saved state to {}", path.display()),
+                    Err(err) => eprintln!("Saving state failed: {}", err),
+                }
+            }
+            Some(Event::LoadState(slot)) => {
+                let path = state_path(filename, slot);
+                match fs::read(&path)
+                    .map_err(StateError::from)
+                    .and_then(|data| cpu.load_state(&data))
+                {
+                    Ok(()) => {
+                        eprintln!("Loaded state from {}", path.display());
+                        rewind.clear();
+                    }
+                    Err(err) => eprintln!("Loading state failed: {}", err),
+                }
+            }
+            Some(Event::Debugger) => show_debugger = !show_debugger,
+            Some(Event::SlowMotion) => slow_motion = !slow_motion,
+            Some(Event::Breakpoint) => {
+                let set = debugger.toggle_breakpoint(cpu.pc());
+                eprintln!(
+                    "Breakpoint {} at {:03X}",
+                    if set { "set" } else { "cleared" },
+                    cpu.pc()
+                );
+            }
+            Some(Event::StepInstruction) if cpu.is_paused() && replay.is_none() => {
+                // a breakpoint on the current instruction doesn't stop it
+                // being stepped, but watchpoints still report what it did
+                debugger.before_tick(&mut cpu);
+                clock.charge(&cpu);
+                match tick(&mut cpu, &mut tracer) {
+                    Ok(()) => {
+                        frame_cycles += 1;
+                        if let Some(Stop::Watch(message)) = debugger.after_tick(&cpu) {
+                            eprintln!("{}", message);
+                        }
+                    }
+                    Err(err) => report_fault(&cpu, &err, &program.source_map),
+                }
+            }
+            Some(Event::StepFrame) if cpu.is_paused() => {
+                debugger.step_frame();
+                cpu.toggle_state();
+            }
+            Some(Event::RunToReturn) if cpu.is_paused() => {
+                debugger.run_to_return(&cpu);
+                cpu.toggle_state();
+            }
+            Some(Event::StepInstruction | Event::StepFrame | Event::RunToReturn) => (),
             Some(Event::Exit) => break 'mainloop,
             None => (),
         }
 
-        let execute = check_timers(&mut timers);
+        let new_speed = if input_driver.is_turbo() {
+            Speed::Turbo(args.turbo)
+        } else if slow_motion {
+            Speed::Slow(args.slow_motion)
+        } else {
+            Speed::Normal
+        };
+        if new_speed != speed {
+            speed = new_speed;
+            scheduler.set_speed(speed.factor());
+            display_driver.set_status(&speed.to_string());
+        }
+
+        let rewinding = input_driver.is_rewinding();
+        for _ in 0..frames {
+            // snapshots are taken, and replayed, once per frame
+            if rewinding {
+                if let Err(err) = rewind.rewind(&mut cpu) {
+                    eprintln!("Rewind failed: {}", err);
+                    rewind.clear();
+                }
+                continue;
+            }
+            if cpu.is_paused() {
+                break;
+            }
+            if frame_cycles == 0 {
+                rewind.push(&cpu);
+            }
 
-        if !cpu.is_paused() && execute.contains(&DoTick::Cpu) {
-            cpu.tick();
+            if let Some(movie) = &replay {
+                let frame = movie.frames().get(replay_frame);
+                let res = match frame {
+                    Some(frame) => play_frame(movie, replay_frame, frame, &mut cpu, &mut tracer),
+                    None => Err(format!("Replay finished after {} frames", replay_frame)),
+                };
+                replay_frame += 1;
+                if let Err(message) = res {
+                    eprintln!("{}", message);
+                    if frame.is_some() {
+                        cpu.toggle_state();
+                    }
+                    replay = None;
+                }
+                if !cpu.is_paused() && debugger.on_frame() {
+                    cpu.toggle_state();
+                    break;
+                }
+                continue;
+            }
+
+            // a frame interrupted by the debugger carries on where it left
+            // off, so the timers still count down once per frame's worth
+            while !clock.is_done() && !cpu.is_paused() && !cpu.has_exited() {
+                if debugger.before_tick(&mut cpu) {
+                    report_breakpoint(&cpu, &program.source_map);
+                    cpu.toggle_state();
+                    break;
+                }
+                clock.charge(&cpu);
+                if let Err(err) = tick(&mut cpu, &mut tracer) {
+                    report_fault(&cpu, &err, &program.source_map);
+                    cpu.toggle_state();
+                } else {
+                    frame_cycles += 1;
+                    if let Some(stop) = debugger.after_tick(&cpu) {
+                        if let Stop::Watch(message) = stop {
+                            eprintln!("{}", message);
+                        }
+                        cpu.toggle_state();
+                    }
+                }
+            }
+            if cpu.is_paused() || cpu.has_exited() {
+                break;
+            }
+
+            cpu.decrement_timers();
+            if let Some(movie) = &mut recording {
+                let frame = Frame {
+                    keys: cpu.keys,
+                    cycles: frame_cycles,
+                };
+                movie.push(frame, &cpu);
+            }
+            frame_cycles = 0;
+            clock.next_frame();
+
+            if debugger.on_frame() {
+                cpu.toggle_state();
+                break;
+            }
         }
 
-        if execute.contains(&DoTick::Display) {
-            display_driver.draw(&cpu.pixels)
+        let panel = show_debugger.then(|| debugger.describe(&cpu));
+        display_driver.draw(&cpu.pixels, cpu.width(), cpu.height(), panel.as_deref());
+
+        audio_driver.set_pattern(
+            cpu.audio_pattern()
+                .map(|pattern| (pattern, cpu.pattern_rate())),
+        );
+        audio_driver.set_playing(!rewinding && !cpu.is_paused() && cpu.sound_timer > 0);
+
+        if cpu.has_exited() {
+            break 'mainloop;
         }
+    }
+
+    end_movie(args, &mut recording, &mut replay);
+    save_rpl_flags(filename, cpu.rpl_flags())?;
+
+    Ok(())
+}
 
-        if !cpu.is_paused() && execute.contains(&DoTick::SoundDelay) {
-            cpu.decrement_timers()
+/// Runs a replayed frame, returning a message if it faults or desyncs.
+#[cfg(feature = "sdl")]
+fn play_frame(
+    movie: &Movie,
+    index: usize,
+    frame: &Frame,
+    cpu: &mut Cpu,
+    tracer: &mut Option<Tracer<BufWriter<File>>>,
+) -> Result<(), String> {
+    cpu.keys = frame.keys;
+    for _ in 0..frame.cycles {
+        if cpu.has_exited() {
+            break;
         }
+        tick(cpu, tracer).map_err(|err| format!("CPU fault: {}\n{}", err, cpu.dump_registers()))?;
+    }
+    cpu.decrement_timers();
+    movie.check(index + 1, cpu).map_err(|err| err.to_string())
+}
 
-        if cpu.sound_timer > 0 {
-            audio_driver.beep()
+/// Saves the recording, if there is one, or stops the replay.
+#[cfg(feature = "sdl")]
+fn end_movie(args: &Args, recording: &mut Option<Movie>, replay: &mut Option<Movie>) {
+    if let (Some(movie), Some(path)) = (recording.take(), &args.record) {
+        match fs::write(path, movie.save()) {
+            Ok(()) => eprintln!(
+                "Recorded {} frames to {}",
+                movie.frames().len(),
+                path.display()
+            ),
+            Err(err) => eprintln!("Saving movie failed: {}", err),
         }
     }
+    if replay.take().is_some() {
+        eprintln!("Replay stopped");
+    }
 }
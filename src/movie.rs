@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{Cpu, Mode, MovieError, Quirks, Rng, RngKind};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u8 = 2;
+
+/// Frames between screen hashes, one a second at 60Hz.
+const CHECKPOINT_INTERVAL: usize = 60;
+
+/// One 60Hz frame of input: the keys held for the whole frame and the
+/// number of instructions run before the timers counted down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub keys: [bool; 16],
+    pub cycles: u32,
+}
+
+/// A recorded session that can be replayed frame for frame. Besides the
+/// input it holds everything else a run depends on (the ROM, mode, quirks,
+/// RNG seed and RPL flags), plus screen hashes to catch a replay that has
+/// drifted from the recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub mode: Mode,
+    pub quirks: Quirks,
+    pub rng: RngKind,
+    pub seed: u64,
+    pub rpl_flags: [u8; 16],
+    rom_hash: u64,
+    frames: Vec<Frame>,
+    checkpoints: BTreeMap<usize, u64>,
+}
+
+impl Movie {
+    /// Starts an empty recording for `cpu`, which must not have run yet and
+    /// whose RNG was created from `seed`.
+    pub fn new(cpu: &Cpu, seed: u64) -> Self {
+        Self {
+            mode: cpu.mode(),
+            quirks: cpu.quirks(),
+            rng: cpu.rng().kind(),
+            seed,
+            rpl_flags: cpu.rpl_flags(),
+            rom_hash: cpu.rom_hash(),
+            frames: vec![],
+            checkpoints: BTreeMap::new(),
+        }
+    }
+
+    /// A fresh `Cpu` set up as the recording's was, ready to replay it.
+    pub fn start(&self, rom: &[u8]) -> Result<Cpu, MovieError> {
+        let rng = match self.rng {
+            RngKind::Xorshift => Rng::new(self.seed),
+            RngKind::Cosmac => Rng::cosmac(self.seed),
+        };
+        let mut cpu = Cpu::builder(rom)
+            .mode(self.mode)
+            .quirks(self.quirks)
+            .rng(rng)
+            .build()
+            .map_err(|_| MovieError::RomMismatch)?;
+        if cpu.rom_hash() != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        cpu.set_rpl_flags(self.rpl_flags);
+        Ok(cpu)
+    }
+
+    /// Appends a frame once it has run, `cpu` being the state after its
+    /// timers counted down.
+    pub fn push(&mut self, frame: Frame, cpu: &Cpu) {
+        self.frames.push(frame);
+        if self.frames.len() % CHECKPOINT_INTERVAL == 0 {
+            self.checkpoints
+                .insert(self.frames.len(), cpu.screen_hash());
+        }
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Compares the screen after `frames` frames have been replayed with
+    /// the recording, if a hash was taken there.
+    pub fn check(&self, frames: usize, cpu: &Cpu) -> Result<(), MovieError> {
+        match self.checkpoints.get(&frames) {
+            Some(&expected) if expected != cpu.screen_hash() => Err(MovieError::Desync {
+                frame: frames,
+                expected,
+                actual: cpu.screen_hash(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Serializes the movie as a magic number and version byte followed by
+    /// the settings, the frames and the checkpoints, all big-endian.
+    pub fn save(&self) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.write_u64::<BigEndian>(self.rom_hash).unwrap();
+        out.push(self.mode as u8);
+        out.push(quirk_bits(self.quirks));
+        out.push(self.rng as u8);
+        out.write_u64::<BigEndian>(self.seed).unwrap();
+        out.extend_from_slice(&self.rpl_flags);
+
+        out.write_u32::<BigEndian>(self.frames.len() as u32)
+            .unwrap();
+        for frame in &self.frames {
+            let keys = (0..16).fold(0, |bits, key| bits | (frame.keys[key] as u16) << key);
+            out.write_u16::<BigEndian>(keys).unwrap();
+            out.write_u32::<BigEndian>(frame.cycles).unwrap();
+        }
+
+        out.write_u32::<BigEndian>(self.checkpoints.len() as u32)
+            .unwrap();
+        for (&frame, &hash) in &self.checkpoints {
+            out.write_u32::<BigEndian>(frame as u32).unwrap();
+            out.write_u64::<BigEndian>(hash).unwrap();
+        }
+        out
+    }
+
+    /// Reads a movie written by `save`.
+    pub fn load(data: &[u8]) -> Result<Movie, MovieError> {
+        let mut cur = Cursor::new(data);
+
+        let mut magic = [0; 4];
+        cur.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = cur.read_u8()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let rom_hash = cur.read_u64::<BigEndian>()?;
+        let mode = match cur.read_u8()? {
+            0 => Mode::Chip8,
+            1 => Mode::XoChip,
+            _ => return Err(MovieError::OutOfRange("mode")),
+        };
+        let quirks = quirks_from_bits(cur.read_u8()?)?;
+        let rng = match cur.read_u8()? {
+            0 => RngKind::Xorshift,
+            1 => RngKind::Cosmac,
+            _ => return Err(MovieError::OutOfRange("RNG kind")),
+        };
+        let seed = cur.read_u64::<BigEndian>()?;
+        let mut rpl_flags = [0; 16];
+        cur.read_exact(&mut rpl_flags)?;
+
+        let mut frames = vec![];
+        for _ in 0..cur.read_u32::<BigEndian>()? {
+            let bits = cur.read_u16::<BigEndian>()?;
+            let mut keys = [false; 16];
+            for (key, held) in keys.iter_mut().enumerate() {
+                *held = bits & 1 << key != 0;
+            }
+            let cycles = cur.read_u32::<BigEndian>()?;
+            frames.push(Frame { keys, cycles });
+        }
+
+        let mut checkpoints = BTreeMap::new();
+        for _ in 0..cur.read_u32::<BigEndian>()? {
+            let frame = cur.read_u32::<BigEndian>()? as usize;
+            checkpoints.insert(frame, cur.read_u64::<BigEndian>()?);
+        }
+
+        Ok(Movie {
+            mode,
+            quirks,
+            rng,
+            seed,
+            rpl_flags,
+            rom_hash,
+            frames,
+            checkpoints,
+        })
+    }
+}
+
+fn quirk_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.shift,
+        quirks.load_store_increment,
+        quirks.vf_reset,
+        quirks.jump_with_offset,
+        quirks.clipping,
+        quirks.display_wait,
+        quirks.resolution_clear,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (bit, &set)| bits | (set as u8) << bit)
+}
+
+fn quirks_from_bits(bits: u8) -> Result<Quirks, MovieError> {
+    if bits >> 7 != 0 {
+        return Err(MovieError::OutOfRange("quirks"));
+    }
+    let set = |bit: u8| bits & 1 << bit != 0;
+    Ok(Quirks {
+        shift: set(0),
+        load_store_increment: set(1),
+        vf_reset: set(2),
+        jump_with_offset: set(3),
+        clipping: set(4),
+        display_wait: set(5),
+        resolution_clear: set(6),
+    })
+}
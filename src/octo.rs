@@ -0,0 +1,1003 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::{AsmError, Mode};
+
+const PROGRAM_START: usize = 0x200;
+
+/// How deep macros can be used inside other macros, so that one that uses
+/// itself is reported rather than expanded forever.
+const MAX_MACRO_DEPTH: usize = 64;
+
+/// Maps ROM addresses back to the Octo source they were compiled from.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    lines: BTreeMap<usize, usize>,
+    source: Vec<String>,
+}
+
+impl SourceMap {
+    /// The 1-based source line of whatever was compiled to `addr`.
+    pub fn line(&self, addr: usize) -> Option<usize> {
+        self.lines.get(&addr).copied()
+    }
+
+    /// `line N: <source>` for `addr`, for reports.
+    pub fn describe(&self, addr: usize) -> Option<String> {
+        let line = self.line(addr)?;
+        let text = self.source.get(line - 1).map_or("", |text| text.trim());
+        Some(format!("line {}: {}", line, text))
+    }
+}
+
+/// A compiled Octo program.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    /// The ROM, to be loaded at 0x200.
+    pub rom: Vec<u8>,
+    pub source_map: SourceMap,
+    /// Addresses marked with `:breakpoint`.
+    pub breakpoints: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    /// How many macro expansions produced this token, 0 for source text.
+    depth: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How to fill in a reference to a label that wasn't defined yet.
+#[derive(Clone, Copy)]
+enum Fixup {
+    /// The low 12 bits of the instruction.
+    Addr,
+    /// A whole 16-bit word.
+    Long,
+    /// The byte operand of `:unpack`'s `v0 :=`, with the given high nibble
+    /// (or the high byte of the address for `:unpack long`).
+    UnpackHi(Option<u8>),
+    /// The byte operand of `:unpack`'s `v1 :=`.
+    UnpackLo,
+}
+
+struct Patch {
+    addr: usize,
+    fixup: Fixup,
+    name: String,
+    line: usize,
+}
+
+enum Block {
+    If {
+        jump: usize,
+        line: usize,
+    },
+    Else {
+        jump: usize,
+        line: usize,
+    },
+    Loop {
+        start: usize,
+        exits: Vec<usize>,
+        line: usize,
+    },
+}
+
+/// Either side of a comparison.
+#[derive(Clone, Copy)]
+enum Operand {
+    Reg(u16),
+    Byte(u16),
+}
+
+/// The instructions for a condition: a prelude, then one skip for when the
+/// condition is false and one for when it is true.
+struct Condition {
+    prelude: Vec<u16>,
+    skip_false: u16,
+    skip_true: u16,
+}
+
+struct Compiler {
+    mode: Mode,
+    tokens: VecDeque<Token>,
+    line: usize,
+    /// The macro depth of the last token read.
+    depth: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    patches: Vec<Patch>,
+    blocks: Vec<Block>,
+    lines: BTreeMap<usize, usize>,
+    breakpoints: Vec<usize>,
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, text) in source.lines().enumerate() {
+        for word in text.split_whitespace() {
+            if word.starts_with('#') {
+                break;
+            }
+            tokens.push_back(Token {
+                text: word.to_string(),
+                line: index + 1,
+                depth: 0,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = text.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = text.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        text.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u16> {
+    let digit = text.strip_prefix('v').or(text.strip_prefix('V'))?;
+    if digit.len() == 1 {
+        u16::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+impl Compiler {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AsmError> {
+        Err(AsmError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                self.depth = token.depth;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected {} but found {}", expected, token));
+        }
+        Ok(())
+    }
+
+    fn require_xo_chip(&self, what: &str) -> Result<(), AsmError> {
+        if self.mode != Mode::XoChip {
+            return self.error(format!("{} needs XO-CHIP mode", what));
+        }
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        let offset = self.here - PROGRAM_START;
+        if self.here >= self.mode.memory_size() {
+            return self.error("program doesn't fit in memory");
+        }
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.lines.insert(self.here, self.line);
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), AsmError> {
+        let [hi, lo] = word.to_be_bytes();
+        self.emit_byte(hi)?;
+        self.emit_byte(lo)?;
+        // only the first byte of an instruction is an address worth mapping
+        self.lines.remove(&(self.here - 1));
+        Ok(())
+    }
+
+    fn register(&mut self) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        match self.as_register(&token) {
+            Some(reg) => Ok(reg),
+            None => self.error(format!("expected a register but found {}", token)),
+        }
+    }
+
+    fn as_register(&self, text: &str) -> Option<u16> {
+        parse_register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    /// A value that must be known now: a number, constant, defined label or
+    /// `{ expression }`.
+    fn value(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        self.value_of(&token)
+    }
+
+    fn value_of(&mut self, token: &str) -> Result<i64, AsmError> {
+        if token == "{" {
+            return Ok(self.calc()? as i64);
+        }
+        match self.lookup(token) {
+            Some(value) => Ok(value as i64),
+            None => self.error(format!("undefined name {}", token)),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<f64> {
+        if let Some(value) = parse_number(name) {
+            Some(value as f64)
+        } else if let Some(&value) = self.constants.get(name) {
+            Some(value)
+        } else {
+            self.labels.get(name).map(|&addr| addr as f64)
+        }
+    }
+
+    fn byte(&mut self) -> Result<u16, AsmError> {
+        let value = self.value()?;
+        if !(-0x80..=0xFF).contains(&value) {
+            return self.error(format!("{} doesn't fit in a byte", value));
+        }
+        Ok((value & 0xFF) as u16)
+    }
+
+    fn nibble(&mut self) -> Result<u16, AsmError> {
+        let value = self.value()?;
+        if !(0..=0xF).contains(&value) {
+            return self.error(format!("{} doesn't fit in a nibble", value));
+        }
+        Ok(value as u16)
+    }
+
+    /// An address operand for the instruction about to be emitted at `here`.
+    /// Labels that aren't defined yet are patched in once they are.
+    fn address(&mut self, fixup: Fixup) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        let max = match fixup {
+            Fixup::Addr => 0xFFF,
+            _ => 0xFFFF,
+        };
+        let value = if token == "{" || self.lookup(&token).is_some() {
+            self.value_of(&token)?
+        } else if parse_register(&token).is_none() && !token.starts_with(':') {
+            self.patches.push(Patch {
+                addr: self.here,
+                fixup,
+                name: token,
+                line: self.line,
+            });
+            return Ok(0);
+        } else {
+            return self.error(format!("expected an address but found {}", token));
+        };
+        if !(0..=max).contains(&value) {
+            return self.error(format!("address {:#X} is out of range", value));
+        }
+        Ok(value as u16)
+    }
+
+    /// Evaluates a `:calc`-style expression up to the closing `}`.
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    /// Like Octo, binary operators have no precedence and group to the
+    /// right, so `2 * 3 + 1` is 8.
+    fn expression(&mut self) -> Result<f64, AsmError> {
+        let lhs = self.term()?;
+        let op = match self.peek() {
+            Some(")" | "}") | None => return Ok(lhs),
+            Some(_) => self.next()?,
+        };
+        let rhs = self.expression()?;
+        let bool = |b: bool| if b { 1.0 } else { 0.0 };
+        Ok(match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" if rhs == 0.0 => return self.error("division by zero"),
+            "/" => lhs / rhs,
+            "%" if rhs == 0.0 => return self.error("division by zero"),
+            "%" => lhs % rhs,
+            "&" => ((lhs as i64) & (rhs as i64)) as f64,
+            "|" => ((lhs as i64) | (rhs as i64)) as f64,
+            "^" => ((lhs as i64) ^ (rhs as i64)) as f64,
+            "<<" => ((lhs as i64) << (rhs as i64 & 63)) as f64,
+            ">>" => ((lhs as i64) >> (rhs as i64 & 63)) as f64,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => bool(lhs < rhs),
+            "<=" => bool(lhs <= rhs),
+            ">" => bool(lhs > rhs),
+            ">=" => bool(lhs >= rhs),
+            "==" => bool(lhs == rhs),
+            "!=" => bool(lhs != rhs),
+            _ => return self.error(format!("unknown operator {}", op)),
+        })
+    }
+
+    fn term(&mut self) -> Result<f64, AsmError> {
+        let token = self.next()?;
+        Ok(match token.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                value
+            }
+            "-" => -self.term()?,
+            "~" => !(self.term()? as i64) as f64,
+            "!" => (self.term()? == 0.0) as i64 as f64,
+            "abs" => self.term()?.abs(),
+            "sqrt" => self.term()?.sqrt(),
+            "sin" => self.term()?.sin(),
+            "cos" => self.term()?.cos(),
+            "tan" => self.term()?.tan(),
+            "exp" => self.term()?.exp(),
+            "log" => self.term()?.ln(),
+            "sign" => self.term()?.signum(),
+            "ceil" => self.term()?.ceil(),
+            "floor" => self.term()?.floor(),
+            "HERE" => self.here as f64,
+            _ => match self.lookup(&token) {
+                Some(value) => value,
+                None => return self.error(format!("undefined name {}", token)),
+            },
+        })
+    }
+
+    fn define_label(&mut self, name: String) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return self.error(format!("{} is already defined", name));
+        }
+        // the jump to main is dropped when main comes first anyway
+        if name == "main" && self.here == PROGRAM_START + 2 && self.rom.len() == 2 {
+            self.rom.clear();
+            self.here = PROGRAM_START;
+            self.lines.remove(&PROGRAM_START);
+            self.patches.retain(|patch| patch.addr != PROGRAM_START);
+        }
+        self.labels.insert(name, self.here);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: String, value: f64) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name) {
+            return self.error(format!("{} is already a label", name));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, AsmError> {
+        let name = self.next()?;
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && parse_register(&name).is_none();
+        if !valid {
+            return self.error(format!("invalid name {}", name));
+        }
+        Ok(name)
+    }
+
+    fn directive(&mut self, directive: &str) -> Result<(), AsmError> {
+        match directive {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.define_constant(name, value as f64)?;
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.define_constant(name, value)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let reg = if token == "{" {
+                    self.calc()? as i64
+                } else {
+                    match self.as_register(&token) {
+                        Some(reg) => reg as i64,
+                        None => {
+                            return self.error(format!("expected a register but found {}", token))
+                        }
+                    }
+                };
+                if !(0..=0xF).contains(&reg) {
+                    return self.error(format!("v{} isn't a register", reg));
+                }
+                self.aliases.insert(name, reg as u16);
+            }
+            ":macro" => {
+                let name = self.name()?;
+                let mut params = vec![];
+                loop {
+                    let token = self.next()?;
+                    if token == "{" {
+                        break;
+                    }
+                    params.push(token);
+                }
+                let mut body = vec![];
+                let mut depth = 1;
+                loop {
+                    let token = self.tokens.pop_front();
+                    let Some(token) = token else {
+                        return self.error(format!("macro {} is missing its closing }}", name));
+                    };
+                    match token.text.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => (),
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    body.push(token);
+                }
+                self.macros.insert(name, Macro { params, body });
+            }
+            ":unpack" => {
+                let hi = match self.next()?.as_str() {
+                    "long" => None,
+                    token => {
+                        let nibble = self.value_of(token)?;
+                        if !(0..=0xF).contains(&nibble) {
+                            return self.error(format!("{} doesn't fit in a nibble", nibble));
+                        }
+                        Some(nibble as u8)
+                    }
+                };
+                let addr = self.address(Fixup::UnpackHi(hi))? as usize;
+                let first = match hi {
+                    Some(nibble) => (nibble as usize) << 4 | (addr >> 8 & 0xF),
+                    None => addr >> 8,
+                };
+                self.emit(0x6000 | first as u16)?;
+                // a forward reference recorded against v0 also fills in v1
+                if let Some(patch) = self.patches.last() {
+                    if patch.addr == self.here - 2 {
+                        let patch = Patch {
+                            addr: self.here,
+                            fixup: Fixup::UnpackLo,
+                            name: patch.name.clone(),
+                            line: patch.line,
+                        };
+                        self.patches.push(patch);
+                    }
+                }
+                self.emit(0x6100 | (addr & 0xFF) as u16)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name.clone())?;
+                self.labels.insert(name, self.here + 1);
+            }
+            ":org" => {
+                let addr = self.value()?;
+                if !(PROGRAM_START as i64..self.mode.memory_size() as i64).contains(&addr) {
+                    return self.error(format!("can't place code at {:#X}", addr));
+                }
+                self.here = addr as usize;
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte as u8)?;
+            }
+            ":pointer" => {
+                let addr = self.address(Fixup::Long)?;
+                self.emit(addr)?;
+            }
+            ":call" => {
+                let addr = self.address(Fixup::Addr)?;
+                self.emit(0x2000 | addr)?;
+            }
+            ":breakpoint" => {
+                self.name()?;
+                self.breakpoints.push(self.here);
+            }
+            ":assert" => {
+                let message = self.next()?;
+                self.expect("{")?;
+                if self.calc()? == 0.0 {
+                    return self.error(format!("assertion failed: {}", message));
+                }
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            _ => return self.error(format!("unsupported directive {}", directive)),
+        }
+        Ok(())
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let token = self.next()?;
+        match self.as_register(&token) {
+            Some(reg) => Ok(Operand::Reg(reg)),
+            None => {
+                let value = self.value_of(&token)?;
+                if !(-0x80..=0xFF).contains(&value) {
+                    return self.error(format!("{} doesn't fit in a byte", value));
+                }
+                Ok(Operand::Byte((value & 0xFF) as u16))
+            }
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let simple = |skip_false, skip_true| Condition {
+            prelude: vec![],
+            skip_false,
+            skip_true,
+        };
+        match op.as_str() {
+            "key" => return Ok(simple(0xE0A1 | x << 8, 0xE09E | x << 8)),
+            "-key" => return Ok(simple(0xE09E | x << 8, 0xE0A1 | x << 8)),
+            _ => (),
+        }
+
+        let rhs = self.operand()?;
+        Ok(match (op.as_str(), rhs) {
+            ("==", Operand::Reg(y)) => simple(0x9000 | x << 8 | y << 4, 0x5000 | x << 8 | y << 4),
+            ("==", Operand::Byte(nn)) => simple(0x4000 | x << 8 | nn, 0x3000 | x << 8 | nn),
+            ("!=", Operand::Reg(y)) => simple(0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            ("!=", Operand::Byte(nn)) => simple(0x3000 | x << 8 | nn, 0x4000 | x << 8 | nn),
+            ("<" | ">" | "<=" | ">=", _) => {
+                // compute the no-borrow flag of p - q into vf; it is set
+                // exactly when p >= q
+                let (p, q) = match op.as_str() {
+                    "<" | ">=" => (Operand::Reg(x), rhs),
+                    _ => (rhs, Operand::Reg(x)),
+                };
+                let prelude = match (p, q) {
+                    (Operand::Reg(p), Operand::Reg(q)) => vec![0x8F00 | p << 4, 0x8F05 | q << 4],
+                    (Operand::Reg(p), Operand::Byte(q)) => vec![0x6F00 | q, 0x8F07 | p << 4],
+                    (Operand::Byte(p), Operand::Reg(q)) => vec![0x6F00 | p, 0x8F05 | q << 4],
+                    (Operand::Byte(_), Operand::Byte(_)) => unreachable!(),
+                };
+                let flag = matches!(op.as_str(), "<=" | ">=") as u16;
+                Condition {
+                    prelude,
+                    skip_false: 0x4F00 | flag,
+                    skip_true: 0x3F00 | flag,
+                }
+            }
+            _ => return self.error(format!("unknown comparison {}", op)),
+        })
+    }
+
+    /// Emits a jump whose target is filled in when the block ends.
+    fn forward_jump(&mut self) -> Result<usize, AsmError> {
+        let addr = self.here;
+        self.emit(0x1000)?;
+        Ok(addr)
+    }
+
+    fn patch_jump(&mut self, jump: usize, target: usize) {
+        let offset = jump - PROGRAM_START;
+        self.rom[offset] = 0x10 | (target >> 8 & 0xF) as u8;
+        self.rom[offset + 1] = target as u8;
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), AsmError> {
+        let op = self.next()?;
+        let token = self.next()?;
+        let y = self.as_register(&token);
+        let word = match (op.as_str(), token.as_str(), y) {
+            (":=", _, Some(y)) => 0x8000 | x << 8 | y << 4,
+            (":=", "random", None) => 0xC000 | x << 8 | self.byte()?,
+            (":=", "key", None) => 0xF00A | x << 8,
+            (":=", "delay", None) => 0xF007 | x << 8,
+            ("+=", _, Some(y)) => 0x8004 | x << 8 | y << 4,
+            ("-=", _, Some(y)) => 0x8005 | x << 8 | y << 4,
+            ("=-", _, Some(y)) => 0x8007 | x << 8 | y << 4,
+            ("|=", _, Some(y)) => 0x8001 | x << 8 | y << 4,
+            ("&=", _, Some(y)) => 0x8002 | x << 8 | y << 4,
+            ("^=", _, Some(y)) => 0x8003 | x << 8 | y << 4,
+            (">>=", _, Some(y)) => 0x8006 | x << 8 | y << 4,
+            ("<<=", _, Some(y)) => 0x800E | x << 8 | y << 4,
+            (":=" | "+=" | "-=", _, None) => {
+                let value = self.value_of(&token)?;
+                let value = if op == "-=" { -value } else { value };
+                if !(-0xFF..=0xFF).contains(&value) {
+                    return self.error(format!("{} doesn't fit in a byte", value));
+                }
+                let nn = (value & 0xFF) as u16;
+                if op == ":=" {
+                    0x6000 | x << 8 | nn
+                } else {
+                    0x7000 | x << 8 | nn
+                }
+            }
+            _ => return self.error(format!("can't use {} {}", op, token)),
+        };
+        self.emit(word)
+    }
+
+    fn i_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        match op.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(0xF01E | x << 8)
+            }
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(0xF029 | x << 8)
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(0xF030 | x << 8)
+                }
+                Some("long") => {
+                    self.next()?;
+                    self.require_xo_chip("i := long")?;
+                    self.emit(0xF000)?;
+                    let addr = self.address(Fixup::Long)?;
+                    self.emit(addr)
+                }
+                _ => {
+                    let addr = self.address(Fixup::Addr)?;
+                    self.emit(0xA000 | addr)
+                }
+            },
+            _ => self.error(format!("can't use i {}", op)),
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+
+        if let Some(reg) = self.as_register(&token) {
+            return self.register_statement(reg);
+        }
+        if token.starts_with(':') {
+            return self.directive(&token);
+        }
+        if self.macros.contains_key(&token) {
+            if self.depth >= MAX_MACRO_DEPTH {
+                return self.error(format!("macro {} expands recursively", token));
+            }
+            let depth = self.depth + 1;
+            let mut args = HashMap::new();
+            for param in self.macros[&token].params.clone() {
+                args.insert(param, self.next()?);
+            }
+            let mac = &self.macros[&token];
+            // expanded code is mapped to the line the macro was used on
+            for body in mac.body.iter().rev() {
+                let text = args.get(&body.text).unwrap_or(&body.text).clone();
+                self.tokens.push_front(Token {
+                    text,
+                    line: self.line,
+                    depth,
+                });
+            }
+            return Ok(());
+        }
+
+        match token.as_str() {
+            "return" | ";" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n)
+            }
+            "scroll-up" => {
+                self.require_xo_chip(&token)?;
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n)
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "native" => {
+                let addr = self.address(Fixup::Addr)?;
+                self.emit(addr)
+            }
+            "jump" => {
+                let addr = self.address(Fixup::Addr)?;
+                self.emit(0x1000 | addr)
+            }
+            "jump0" => {
+                let addr = self.address(Fixup::Addr)?;
+                self.emit(0xB000 | addr)
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(0xF033 | x << 8)
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    self.require_xo_chip("register ranges")?;
+                    let y = self.register()?;
+                    let op = if token == "save" { 0x5002 } else { 0x5003 };
+                    self.emit(op | x << 8 | y << 4)
+                } else {
+                    let op = if token == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(op | x << 8)
+                }
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(0xF075 | x << 8)
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(0xF085 | x << 8)
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let op = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => {
+                        self.require_xo_chip(&token)?;
+                        0xF03A
+                    }
+                };
+                self.emit(op | x << 8)
+            }
+            "plane" => {
+                self.require_xo_chip(&token)?;
+                let n = self.nibble()?;
+                if n > 3 {
+                    return self.error(format!("plane {} doesn't exist", n));
+                }
+                self.emit(0xF001 | n << 8)
+            }
+            "audio" => {
+                self.require_xo_chip(&token)?;
+                self.emit(0xF002)
+            }
+            "i" => self.i_statement(),
+            "if" => {
+                let line = self.line;
+                let condition = self.condition()?;
+                for word in &condition.prelude {
+                    self.emit(*word)?;
+                }
+                match self.next()?.as_str() {
+                    "then" => {
+                        self.emit(condition.skip_false)?;
+                        self.statement()
+                    }
+                    "begin" => {
+                        self.emit(condition.skip_true)?;
+                        let jump = self.forward_jump()?;
+                        self.blocks.push(Block::If { jump, line });
+                        Ok(())
+                    }
+                    other => self.error(format!("expected then or begin but found {}", other)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, line }) => {
+                    let end = self.forward_jump()?;
+                    self.patch_jump(jump, self.here);
+                    self.blocks.push(Block::Else { jump: end, line });
+                    Ok(())
+                }
+                _ => self.error("else without if ... begin"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. } | Block::Else { jump, .. }) => {
+                    self.patch_jump(jump, self.here);
+                    Ok(())
+                }
+                _ => self.error("end without if ... begin"),
+            },
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                    start: self.here,
+                    exits: vec![],
+                    line: self.line,
+                });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                for word in &condition.prelude {
+                    self.emit(*word)?;
+                }
+                self.emit(condition.skip_true)?;
+                let jump = self.forward_jump()?;
+                let innermost = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                });
+                match innermost {
+                    Some(exits) => {
+                        exits.push(jump);
+                        Ok(())
+                    }
+                    None => self.error("while outside of a loop"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    self.emit(0x1000 | start as u16)?;
+                    for exit in exits {
+                        self.patch_jump(exit, self.here);
+                    }
+                    Ok(())
+                }
+                _ => self.error("again without loop"),
+            },
+            _ => {
+                // an assignment to something that isn't a register, rather
+                // than a call followed by a stray operator
+                if matches!(
+                    self.peek(),
+                    Some(":=" | "+=" | "-=" | "=-" | "|=" | "&=" | "^=" | ">>=" | "<<=")
+                ) {
+                    return self.error(format!("expected a register but found {}", token));
+                }
+                if let Some(value) = self.lookup(&token) {
+                    if self.labels.contains_key(&token) {
+                        return self.emit(0x2000 | (value as u16 & 0xFFF));
+                    }
+                    let value = value as i64;
+                    if !(-0x80..=0xFF).contains(&value) {
+                        return self.error(format!("{} doesn't fit in a byte", value));
+                    }
+                    return self.emit_byte(value as u8);
+                }
+                // anything else is a call to a label defined later on
+                self.tokens.push_front(Token {
+                    text: token,
+                    line: self.line,
+                    depth: self.depth,
+                });
+                let addr = self.address(Fixup::Addr)?;
+                self.emit(0x2000 | addr)
+            }
+        }
+    }
+
+    fn resolve(&mut self) -> Result<(), AsmError> {
+        for patch in std::mem::take(&mut self.patches) {
+            self.line = patch.line;
+            let Some(&addr) = self.labels.get(&patch.name) else {
+                return self.error(format!("undefined label {}", patch.name));
+            };
+            let offset = patch.addr - PROGRAM_START;
+            match patch.fixup {
+                Fixup::Addr => {
+                    if addr > 0xFFF {
+                        return self
+                            .error(format!("{} at {:#X} is out of range", patch.name, addr));
+                    }
+                    self.rom[offset] |= (addr >> 8) as u8;
+                    self.rom[offset + 1] = addr as u8;
+                }
+                Fixup::Long => {
+                    self.rom[offset] = (addr >> 8) as u8;
+                    self.rom[offset + 1] = addr as u8;
+                }
+                Fixup::UnpackHi(Some(nibble)) => {
+                    self.rom[offset + 1] = nibble << 4 | (addr >> 8 & 0xF) as u8;
+                }
+                Fixup::UnpackHi(None) => self.rom[offset + 1] = (addr >> 8) as u8,
+                Fixup::UnpackLo => self.rom[offset + 1] = addr as u8,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compiles an Octo program, with its structured control flow (`if`/`then`,
+/// `if`/`begin`/`else`/`end`, `loop`/`while`/`again`), `:alias`, `:macro`,
+/// `:calc`, `:const` and the other common directives. XO-CHIP instructions
+/// are only accepted in XO-CHIP mode. As in Octo, execution starts with a
+/// jump to `main` unless `main` comes first.
+pub fn compile(source: &str, mode: Mode) -> Result<Program, AsmError> {
+    let mut compiler = Compiler {
+        mode,
+        tokens: tokenize(source),
+        line: 1,
+        depth: 0,
+        rom: vec![],
+        here: PROGRAM_START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        patches: vec![],
+        blocks: vec![],
+        lines: BTreeMap::new(),
+        breakpoints: vec![],
+    };
+
+    compiler.tokens.push_front(Token {
+        text: "main".to_string(),
+        line: 1,
+        depth: 0,
+    });
+    let addr = compiler.address(Fixup::Addr)?;
+    compiler.emit(0x1000 | addr)?;
+    compiler.lines.clear();
+
+    while !compiler.tokens.is_empty() {
+        compiler.statement()?;
+    }
+
+    if let Some(block) = compiler.blocks.pop() {
+        let (Block::If { line, .. } | Block::Else { line, .. } | Block::Loop { line, .. }) = block;
+        compiler.line = line;
+        let what = match block {
+            Block::Loop { .. } => "loop without again",
+            _ => "if ... begin without end",
+        };
+        return compiler.error(what);
+    }
+    compiler.resolve()?;
+
+    Ok(Program {
+        rom: compiler.rom,
+        source_map: SourceMap {
+            lines: compiler.lines,
+            source: source.lines().map(str::to_string).collect(),
+        },
+        breakpoints: compiler.breakpoints,
+    })
+}
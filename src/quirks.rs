@@ -0,0 +1,66 @@
+/// Behaviour switches for the opcodes that different CHIP-8 interpreters
+/// disagree on. The presets below give the combination each interpreter
+/// used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// When set, 8XY6/8XYE shift Vx in place; otherwise they shift Vy into
+    /// Vx.
+    pub shift: bool,
+    /// When set, FX55/FX65 leave I pointing past the last register
+    /// stored/loaded; otherwise I is unchanged.
+    pub load_store_increment: bool,
+    /// When set, 8XY1/8XY2/8XY3 reset VF to 0; otherwise VF is untouched.
+    pub vf_reset: bool,
+    /// When set, BNNN jumps to XNN + Vx; otherwise to NNN + V0.
+    pub jump_with_offset: bool,
+    /// When set, sprites are clipped at the screen edges; otherwise they
+    /// wrap round.
+    pub clipping: bool,
+    /// When set, DXYN waits for the next vertical blank before drawing;
+    /// otherwise it draws straight away.
+    pub display_wait: bool,
+    /// When set, 00FE/00FF clear the screen, as Octo does; otherwise the
+    /// picture is kept, as SUPER-CHIP 1.1 does.
+    pub resolution_clear: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        load_store_increment: true,
+        vf_reset: true,
+        jump_with_offset: false,
+        clipping: true,
+        display_wait: true,
+        resolution_clear: false,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        load_store_increment: false,
+        vf_reset: false,
+        jump_with_offset: true,
+        clipping: true,
+        display_wait: false,
+        resolution_clear: false,
+    };
+
+    /// SUPER-CHIP 1.1 kept all of CHIP-48's quirks.
+    pub const SUPER_CHIP: Quirks = Quirks::CHIP_48;
+
+    pub const MODERN: Quirks = Quirks {
+        shift: false,
+        load_store_increment: true,
+        vf_reset: false,
+        jump_with_offset: false,
+        clipping: false,
+        display_wait: false,
+        resolution_clear: true,
+    };
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::COSMAC_VIP
+    }
+}
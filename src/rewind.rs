@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+use crate::{Cpu, StateError};
+
+/// Differing bytes this close together are kept in one run, as a new run
+/// costs more than a few unchanged bytes.
+const MERGE_GAP: usize = 8;
+
+/// How to get from one save state to another: the XOR of the two, keeping
+/// only the runs of bytes that differ. Consecutive frames usually differ
+/// in a handful of registers, a few bytes of memory and some pixels, so
+/// this is much smaller than a full state.
+struct Delta {
+    len: usize,
+    /// Offsets and lengths of the runs, whose bytes are stored back to back
+    /// in `bytes`.
+    runs: Vec<(u32, u32)>,
+    bytes: Vec<u8>,
+}
+
+impl Delta {
+    /// The delta that turns `to` back into `from`.
+    fn between(from: &[u8], to: &[u8]) -> Self {
+        let len = from.len().max(to.len());
+        let byte = |state: &[u8], at: usize| state.get(at).copied().unwrap_or(0);
+        let xor = |at: usize| byte(from, at) ^ byte(to, at);
+
+        let mut delta = Self {
+            len: from.len(),
+            runs: vec![],
+            bytes: vec![],
+        };
+        let mut at = 0;
+        while at < len {
+            if xor(at) == 0 {
+                at += 1;
+                continue;
+            }
+            let start = at;
+            let mut last = at;
+            let mut end = at + 1;
+            while end < len && end - last <= MERGE_GAP {
+                if xor(end) != 0 {
+                    last = end;
+                }
+                end += 1;
+            }
+            let end = last + 1;
+            delta.runs.push((start as u32, (end - start) as u32));
+            delta.bytes.extend((start..end).map(xor));
+            at = end;
+        }
+        delta
+    }
+
+    fn apply(&self, to: &[u8]) -> Vec<u8> {
+        let mut state = to.to_vec();
+        state.resize(state.len().max(self.len), 0);
+        let mut bytes = self.bytes.iter();
+        for &(start, len) in &self.runs {
+            let start = start as usize;
+            for (byte, diff) in state[start..start + len as usize]
+                .iter_mut()
+                .zip(&mut bytes)
+            {
+                *byte ^= diff;
+            }
+        }
+        state.truncate(self.len);
+        state
+    }
+}
+
+/// A bounded history of `Cpu` snapshots, oldest first. Once `depth`
+/// snapshots are held, each new one pushes out the oldest.
+///
+/// Only the newest snapshot is kept as a full save state; each older one is
+/// stored as the delta from the snapshot after it.
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    depth: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            latest: None,
+            deltas: VecDeque::new(),
+            depth,
+        }
+    }
+
+    pub fn push(&mut self, cpu: &Cpu) {
+        if self.depth == 0 {
+            return;
+        }
+        let state = cpu.save_state();
+        if let Some(previous) = self.latest.replace(state) {
+            let newest = self.latest.as_deref().unwrap();
+            self.deltas.push_back(Delta::between(&previous, newest));
+        }
+        while self.len() > self.depth {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Restores the most recent snapshot and drops it from the buffer.
+    /// Returns false once there is no more history to go back to.
+    pub fn rewind(&mut self, cpu: &mut Cpu) -> Result<bool, StateError> {
+        let Some(latest) = self.latest.take() else {
+            return Ok(false);
+        };
+        self.latest = self.deltas.pop_back().map(|delta| delta.apply(&latest));
+        cpu.load_state(&latest)?;
+        Ok(true)
+    }
+
+    pub fn len(&self) -> usize {
+        self.latest.iter().count() + self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear()
+    }
+
+    /// Bytes used by the snapshots, not counting allocator overhead.
+    pub fn size(&self) -> usize {
+        let deltas: usize = self
+            .deltas
+            .iter()
+            .map(|delta| delta.bytes.len() + delta.runs.len() * 8)
+            .sum();
+        self.latest.as_ref().map_or(0, Vec::len) + deltas
+    }
+}
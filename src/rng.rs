@@ -0,0 +1,77 @@
+/// The random number generator behind CXNN. It is fully determined by its
+/// seed, so runs, save states and replays are reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    pub(crate) kind: RngKind,
+    pub(crate) state: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngKind {
+    /// xorshift64*
+    Xorshift,
+    /// An approximation of the COSMAC VIP interpreter's routine, which adds
+    /// bytes from its own code page (0x100-0x1FF) to a running total,
+    /// indexed by a counter that advances on every call and on every 60Hz
+    /// interrupt. The counter and total work the same, but the bytes come
+    /// from 0x100-0x1FF of the emulated memory, which holds the end of the
+    /// large font and zeros rather than the VIP's code, so the numbers
+    /// aren't the sequence a real VIP would give.
+    Cosmac,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // run the seed through splitmix64 so that small seeds (and 0, which
+        // xorshift can never leave) still give a well mixed state
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+
+        Self {
+            kind: RngKind::Xorshift,
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    /// The counter and running total start from the low two bytes of `seed`.
+    pub fn cosmac(seed: u64) -> Self {
+        Self {
+            kind: RngKind::Cosmac,
+            state: seed & 0xFFFF,
+        }
+    }
+
+    pub fn kind(&self) -> RngKind {
+        self.kind
+    }
+
+    /// Next random byte, covering the full 0-255 range. `memory` is only
+    /// read in COSMAC mode.
+    pub(crate) fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        match self.kind {
+            RngKind::Xorshift => {
+                self.state ^= self.state >> 12;
+                self.state ^= self.state << 25;
+                self.state ^= self.state >> 27;
+                (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8
+            }
+            RngKind::Cosmac => {
+                let counter = (self.state as u8).wrapping_add(1);
+                let total = (self.state >> 8) as u8;
+                let total = total.wrapping_add(memory[0x100 + counter as usize]) ^ counter;
+                self.state = (total as u64) << 8 | counter as u64;
+                total
+            }
+        }
+    }
+
+    /// Called on every 60Hz interrupt.
+    pub(crate) fn interrupt(&mut self) {
+        if self.kind == RngKind::Cosmac {
+            let counter = (self.state as u8).wrapping_add(1);
+            self.state = (self.state & 0xFF00) | counter as u64;
+        }
+    }
+}
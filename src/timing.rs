@@ -0,0 +1,128 @@
+use crate::Cpu;
+
+/// CDP1802 machine cycles (8 clocks at 1.7609 MHz) in one 60Hz frame.
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+
+/// Machine cycles of each frame the CHIP-8 interpreter doesn't get: the
+/// CDP1861 takes 1024 for display DMA (128 lines of 8 bytes), and the
+/// interrupt routine that sets it up and counts the timers down takes the
+/// rest.
+pub const VIP_INTERRUPT_CYCLES: u32 = 1078;
+
+/// Fetching an instruction, decoding it and jumping to its routine, which
+/// every instruction pays.
+const FETCH: u32 = 40;
+
+/// Extra cycles a skip instruction takes when it skips.
+const SKIP: u32 = 4;
+
+/// How much of each frame a program gets to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// A fixed number of instructions per frame, whatever they are.
+    Instructions(u32),
+    /// As many instructions as fit in a frame on the COSMAC VIP, each
+    /// costing what `vip_cycles` says.
+    CosmacVip,
+}
+
+/// Approximate cost in machine cycles of the instruction at PC on the
+/// COSMAC VIP, including the fetch. The costs follow the structure of the
+/// original interpreter's routines: arithmetic goes through a block of
+/// self-modifying code, BCD counts by repeated subtraction, loads and stores
+/// loop over the registers and sprites are shifted into place bit by bit
+/// when X isn't a multiple of 8.
+///
+/// Waiting for the display before a DXYN isn't included, see
+/// `FrameClock::charge`. Instructions the VIP interpreter didn't have cost
+/// the same as a register load.
+pub fn vip_cycles(cpu: &Cpu) -> u32 {
+    let Some(ins) = cpu.current_instruction() else {
+        return FETCH;
+    };
+    let vx = cpu.v()[ins.x];
+    let vy = cpu.v()[ins.y];
+    let skip = |skips: bool| if skips { SKIP } else { 0 };
+
+    FETCH
+        + match (ins.op, ins.nn) {
+            // the display page is cleared a byte at a time
+            (0x0, 0xE0) => 24 + 256 * 4,
+            (0x0, 0xEE) => 10,
+            (0x1, _) => 12,
+            (0x2, _) => 26,
+            (0x3, _) => 10 + skip(vx == ins.nn),
+            (0x4, _) => 10 + skip(vx != ins.nn),
+            (0x5, _) => 18 + skip(vx == vy),
+            (0x6, _) => 6,
+            (0x7, _) => 10,
+            (0x8, _) => 44,
+            (0x9, _) => 18 + skip(vx != vy),
+            (0xA, _) => 12,
+            (0xB, _) => 22,
+            (0xC, _) => 36,
+            (0xD, _) => {
+                let shift = 4 * (vx % 8) as u32;
+                26 + ins.n as u32 * (34 + shift)
+            }
+            (0xE, 0x9E) => 18 + skip(cpu.keys[(vx & 0xF) as usize]),
+            (0xE, 0xA1) => 18 + skip(!cpu.keys[(vx & 0xF) as usize]),
+            (0xF, 0x1E) => 18,
+            (0xF, 0x29) => 20,
+            (0xF, 0x33) => {
+                let digits = (vx / 100 + vx / 10 % 10 + vx % 10) as u32;
+                80 + 16 * digits
+            }
+            (0xF, 0x55 | 0x65) => 28 + 14 * (ins.x as u32 + 1),
+            _ => 10,
+        }
+}
+
+/// Keeps track of how much of the current frame has been used, to decide
+/// when the timers should count down.
+#[derive(Debug, Clone)]
+pub struct FrameClock {
+    timing: Timing,
+    used: u32,
+}
+
+impl FrameClock {
+    pub fn new(timing: Timing) -> Self {
+        Self { timing, used: 0 }
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    fn budget(&self) -> u32 {
+        match self.timing {
+            Timing::Instructions(ipf) => ipf,
+            Timing::CosmacVip => VIP_FRAME_CYCLES - VIP_INTERRUPT_CYCLES,
+        }
+    }
+
+    /// True once the frame has no time left for another instruction.
+    pub fn is_done(&self) -> bool {
+        self.used >= self.budget()
+    }
+
+    /// Accounts for the instruction `cpu` is about to run. On the VIP a
+    /// DXYN waiting for the display idles until the next interrupt, so it
+    /// uses up the rest of the frame.
+    pub fn charge(&mut self, cpu: &Cpu) {
+        match self.timing {
+            Timing::Instructions(_) => self.used += 1,
+            Timing::CosmacVip if cpu.is_waiting_for_display() => {
+                self.used = self.used.max(self.budget())
+            }
+            Timing::CosmacVip => self.used += vip_cycles(cpu),
+        }
+    }
+
+    /// Starts the next frame. An instruction that ran past the end of the
+    /// last one eats into it.
+    pub fn next_frame(&mut self) {
+        self.used = self.used.saturating_sub(self.budget());
+    }
+}
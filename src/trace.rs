@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::{Cpu, Instruction};
+
+/// Logs every executed instruction, one line each:
+///
+/// ```text
+/// 0202: A22A LD I, #22A             I=022A
+/// 0204: 600C LD V0, #0C             V0=0C
+/// 0208: D01F DRW V0, V1, 15
+/// ```
+///
+/// That is the PC, the raw opcode, the Cowgod mnemonic and the registers
+/// the instruction changed, with their new values. Lines only depend on
+/// the program, so traces of the same ROM can be diffed against each other
+/// or against another emulator's output in the same format.
+pub struct Tracer<W: Write> {
+    out: W,
+    range: Option<RangeInclusive<usize>>,
+    limit: Option<u64>,
+    written: u64,
+    before: Option<(usize, Option<Instruction>, [u8; 16], u16)>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Self {
+        Tracer {
+            out,
+            range: None,
+            limit: None,
+            written: 0,
+            before: None,
+        }
+    }
+
+    /// Only log instructions whose address is in `range`.
+    pub fn set_range(&mut self, range: RangeInclusive<usize>) {
+        self.range = Some(range);
+    }
+
+    /// Stop logging once `bytes` have been written.
+    pub fn set_limit(&mut self, bytes: u64) {
+        self.limit = Some(bytes);
+    }
+
+    fn is_full(&self) -> bool {
+        self.limit.is_some_and(|limit| self.written >= limit)
+    }
+
+    /// Call before each instruction, to note what it starts from.
+    pub fn before_tick(&mut self, cpu: &Cpu) {
+        let wanted = match &self.range {
+            Some(range) => range.contains(&cpu.pc()),
+            None => true,
+        };
+        self.before = (wanted && !self.is_full())
+            .then(|| (cpu.pc(), cpu.current_instruction(), *cpu.v(), cpu.i()));
+    }
+
+    /// Call after each instruction that executed, to log it.
+    pub fn after_tick(&mut self, cpu: &Cpu) -> io::Result<()> {
+        let Some((pc, instruction, v, i)) = self.before.take() else {
+            return Ok(());
+        };
+
+        let mut line = match instruction {
+            Some(ins) => format!("{:04X}: {:04X} {:<22}", pc, ins.full, ins.to_string()),
+            None => format!("{:04X}: ???? {:<22}", pc, ""),
+        };
+        for (reg, (old, new)) in v.iter().zip(cpu.v()).enumerate() {
+            if old != new {
+                line += &format!(" V{:X}={:02X}", reg, new);
+            }
+        }
+        if i != cpu.i() {
+            line += &format!(" I={:04X}", cpu.i());
+        }
+        let mut line = line.trim_end().to_string();
+        line.push('\n');
+
+        self.written += line.len() as u64;
+        self.out.write_all(line.as_bytes())?;
+        if self.is_full() {
+            writeln!(self.out, "# trace stopped after {} bytes", self.written)?;
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::{Access, Cpu};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+}
+
+impl Register {
+    fn value(self, v: &[u8; 16], i: u16) -> u16 {
+        match self {
+            Register::V(reg) => v[reg] as u16,
+            Register::I => i,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(reg) => write!(f, "V{:X}", reg),
+            Register::I => write!(f, "I"),
+        }
+    }
+}
+
+/// A condition that halts execution once an instruction meets it.
+///
+/// Parsed from `ADDR[-ADDR][:r|:w|:rw]` for memory (hex addresses, both
+/// reads and writes by default) or `REG[=VALUE]` for registers, e.g.
+/// `300-30F:w`, `VF=1` or `I`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    Memory {
+        range: RangeInclusive<usize>,
+        read: bool,
+        write: bool,
+    },
+    /// Fires when the register changes, or only when it changes to `value`.
+    Register { reg: Register, value: Option<u16> },
+}
+
+impl Watchpoint {
+    pub fn is_memory(&self) -> bool {
+        matches!(self, Watchpoint::Memory { .. })
+    }
+
+    /// Describes why the instruction just run by `cpu` triggered this
+    /// watchpoint, given the registers from before it ran.
+    pub(crate) fn check(&self, cpu: &Cpu, v: &[u8; 16], i: u16) -> Option<String> {
+        match self {
+            Watchpoint::Memory { range, read, write } => cpu.accesses().iter().find_map(|access| {
+                let hit = range.contains(&access.addr)
+                    && match access.access {
+                        Access::Read => *read,
+                        Access::Write => *write,
+                    };
+                hit.then(|| {
+                    let verb = match access.access {
+                        Access::Read => "read",
+                        Access::Write => "write",
+                    };
+                    format!("{} {:03X}", verb, access.addr)
+                })
+            }),
+            Watchpoint::Register { reg, value } => {
+                let old = reg.value(v, i);
+                let new = reg.value(cpu.v(), cpu.i());
+                let hit = old != new && (value.is_none() || *value == Some(new));
+                hit.then(|| format!("{} {:X} -> {:X}", reg, old, new))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Memory { range, read, write } => {
+                write!(f, "{:03X}", range.start())?;
+                if range.end() != range.start() {
+                    write!(f, "-{:03X}", range.end())?;
+                }
+                match (read, write) {
+                    (true, false) => write!(f, ":r"),
+                    (false, true) => write!(f, ":w"),
+                    _ => write!(f, ":rw"),
+                }
+            }
+            Watchpoint::Register { reg, value } => match value {
+                Some(value) => write!(f, "{}={:X}", reg, value),
+                None => write!(f, "{}", reg),
+            },
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<usize, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number {}", s))
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+
+        let (name, value) = match upper.split_once('=') {
+            Some((name, value)) => (name, Some(parse_hex(value)?)),
+            None => (upper.as_str(), None),
+        };
+        let reg = match name {
+            "I" => Some(Register::I),
+            _ => name
+                .strip_prefix('V')
+                .and_then(|reg| usize::from_str_radix(reg, 16).ok())
+                .filter(|&reg| reg < 16)
+                .map(Register::V),
+        };
+        if let Some(reg) = reg {
+            let value = value
+                .map(|value| {
+                    let fits = match reg {
+                        Register::V(_) => u8::try_from(value).map(u16::from),
+                        Register::I => u16::try_from(value),
+                    };
+                    fits.map_err(|_| format!("{:X} is out of range for {}", value, reg))
+                })
+                .transpose()?;
+            return Ok(Watchpoint::Register { reg, value });
+        }
+        if value.is_some() {
+            return Err(format!("invalid register {}", name));
+        }
+
+        let (range, access) = upper.split_once(':').unwrap_or((&upper, "RW"));
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_hex(start)?, parse_hex(end)?),
+            None => (parse_hex(range)?, parse_hex(range)?),
+        };
+        let (read, write) = match access {
+            "R" => (true, false),
+            "W" => (false, true),
+            "RW" | "WR" => (true, true),
+            _ => return Err(format!("invalid access {}, expected r, w or rw", access)),
+        };
+        Ok(Watchpoint::Memory {
+            range: start.min(end)..=start.max(end),
+            read,
+            write,
+        })
+    }
+}
@@ -0,0 +1,94 @@
+//! Assembles Cowgod-syntax source, and checks errors point at the right
+//! line.
+
+use std::path::Path;
+
+use chip8::{assemble, AsmError, Mode};
+
+fn asm(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble(source, Mode::Chip8, Path::new("."))
+}
+
+fn error(source: &str) -> AsmError {
+    asm(source).expect_err("source should not assemble")
+}
+
+#[test]
+fn assembles_labels_constants_and_data() {
+    let rom = asm("
+SPEED EQU 3
+start:
+    LD V0, SPEED + 1
+    LD I, sprite
+    DRW V0, V1, 2
+    JP start
+sprite:
+    DB %#..#.#.., #FF
+    DW $1234
+")
+    .unwrap();
+    assert_eq!(
+        rom,
+        [0x60, 0x04, 0xA2, 0x08, 0xD0, 0x12, 0x12, 0x00, 0x94, 0xFF, 0x12, 0x34]
+    );
+}
+
+#[test]
+fn rejects_bad_mnemonics() {
+    let err = error("CLS\nFROB V0\nRET");
+    assert_eq!(err.line, 2);
+    assert!(err.message.contains("invalid instruction FROB"), "{}", err);
+
+    // a real mnemonic with operands it doesn't take
+    assert_eq!(error("CLS V0").line, 1);
+    // XO-CHIP instructions need XO-CHIP mode
+    let err = error("CLS\nPLANE 1");
+    assert_eq!(err.line, 2);
+    assert!(err.message.contains("XO-CHIP"), "{}", err);
+}
+
+#[test]
+fn rejects_out_of_range_operands() {
+    let cases = [
+        ("LD V0, 256", "byte 256"),
+        ("LD V0, -129", "byte -129"),
+        ("JP #1000", "address 4096"),
+        ("DRW V0, V1, 16", "nibble 16"),
+        ("DB 300", "out of range"),
+        ("DB -129", "out of range"),
+        ("DW 65536", "out of range"),
+        ("DW -32769", "out of range"),
+        (
+            "DW 9223372036854775807 + 1",
+            "9223372036854775807 + 1 is out of range",
+        ),
+        ("LD V0, 0 - 9223372036854775807 - 2", "out of range"),
+        ("LD V0, missing", "undefined label or constant missing"),
+    ];
+    for (line, message) in cases {
+        let err = error(&format!("CLS\n{}", line));
+        assert_eq!(err.line, 2, "{}", line);
+        assert!(err.message.contains(message), "{}: {}", line, err);
+    }
+}
+
+#[test]
+fn reports_the_line_that_overflows_memory() {
+    // 0xE00 bytes fit between 0x200 and the end of memory
+    let mut source = String::new();
+    for _ in 0..0xE00 / 2 {
+        source += "CLS\n";
+    }
+    assert_eq!(asm(&source).unwrap().len(), 0xE00);
+
+    source += "CLS\n; trailing comment\n\nRET\n";
+    let err = error(&source);
+    assert_eq!(err.line, 0xE00 / 2 + 1);
+    assert!(err.message.contains("only 3584 fit"), "{}", err);
+}
+
+#[test]
+fn writes_negative_data_as_twos_complement() {
+    assert_eq!(asm("DB -1, -128").unwrap(), [0xFF, 0x80]);
+    assert_eq!(asm("DW -1, -32768").unwrap(), [0xFF, 0xFF, 0x80, 0x00]);
+}
@@ -0,0 +1,86 @@
+//! Disassembles ROMs and checks that assembling the output gives the same
+//! bytes back.
+
+use std::fs;
+use std::path::Path;
+
+use chip8::{assemble, compile, disassemble, Mode, Syntax};
+
+fn octo(name: &str, mode: Mode) -> Vec<u8> {
+    let path = format!("tests/roms/{}.8o", name);
+    let source = fs::read_to_string(&path).unwrap();
+    compile(&source, mode)
+        .unwrap_or_else(|err| panic!("{}: {}", path, err))
+        .rom
+}
+
+/// Disassembles `rom` in both syntaxes and reassembles each.
+fn round_trip(rom: &[u8], mode: Mode) {
+    let source = disassemble(rom, Syntax::Octo, mode);
+    let octo = compile(&source, mode).unwrap_or_else(|err| panic!("{}\n{}", err, source));
+    assert_eq!(octo.rom, rom, "{}", source);
+
+    let source = disassemble(rom, Syntax::Cowgod, mode);
+    let cowgod =
+        assemble(&source, mode, Path::new(".")).unwrap_or_else(|err| panic!("{}\n{}", err, source));
+    assert_eq!(cowgod, rom, "{}", source);
+}
+
+#[test]
+fn ibm_logo() {
+    round_trip(&fs::read("ibm_logo.ch8").unwrap(), Mode::Chip8);
+}
+
+#[test]
+fn octo_fixtures() {
+    round_trip(&octo("opcodes", Mode::Chip8), Mode::Chip8);
+    round_trip(&octo("schip", Mode::Chip8), Mode::Chip8);
+    round_trip(&octo("xo", Mode::XoChip), Mode::XoChip);
+}
+
+#[test]
+fn code_after_skips() {
+    let rom = [
+        0x12, 0x04, // 200: jump 204
+        0xFF, 0xFF, // 202: never reached
+        0x30, 0x01, // 204: skip if v0 == 1
+        0x12, 0x0A, // 206: jump 20A
+        0x60, 0x02, // 208: only reached by skipping
+        0x12, 0x0A, // 20A: jump 20A
+    ];
+    let source = disassemble(&rom, Syntax::Cowgod, Mode::Chip8);
+    assert!(source.contains("DB #FF, #FF"), "{}", source);
+    assert!(source.contains("LD V0, #02"), "{}", source);
+    round_trip(&rom, Mode::Chip8);
+}
+
+#[test]
+fn long_i_load() {
+    let rom = [
+        0xF0, 0x00, 0x00, 0xE0, // 200: i := long 0E0
+        0xF0, 0x00, 0x02, 0x0C, // 204: i := long 20C
+        0x12, 0x08, // 208: jump 208
+        0x00, 0x00, // 20A: padding
+        0x60, 0x01, // 20C: sprite data that looks like code
+    ];
+    let source = disassemble(&rom, Syntax::Octo, Mode::XoChip);
+    // the address after F000 isn't decoded as an instruction of its own
+    assert!(source.contains("i := long 0x0E0"), "{}", source);
+    assert!(!source.contains("clear"), "{}", source);
+    assert!(source.contains("i := long data_20C"), "{}", source);
+    assert!(!source.contains("v0 := 0x01"), "{}", source);
+    round_trip(&rom, Mode::XoChip);
+}
+
+#[test]
+fn zero_opcodes_with_a_register() {
+    let rom = [
+        0x00, 0xE0, // 200: clear
+        0x01, 0xE0, // 202: not an instruction, as X isn't 0
+        0x12, 0x02, // 204: jump 202
+    ];
+    let source = disassemble(&rom, Syntax::Cowgod, Mode::Chip8);
+    assert_eq!(source.matches("CLS").count(), 1, "{}", source);
+    assert!(source.contains("DB #01, #E0"), "{}", source);
+    round_trip(&rom, Mode::Chip8);
+}
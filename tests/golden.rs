@@ -0,0 +1,230 @@
+//! Runs test ROMs for a fixed number of instructions and compares the final
+//! screen against the golden images in `tests/golden`. Set `UPDATE_GOLDEN=1`
+//! to write the current screens as the new golden images instead.
+//!
+//! Timendus' chip8-test-suite ROMs aren't included. Their tests return early
+//! without checking anything unless the ROMs have been copied into
+//! `tests/roms/timendus`. Their golden images have to be recorded by hand:
+//! check that the screen printed by the failing test shows every test
+//! passing before recording it with `UPDATE_GOLDEN=1`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::{compile, Cpu, Mode, Quirks, Rng};
+
+/// Instructions per 60Hz frame, as with the default 1000Hz clock.
+const CYCLES_PER_FRAME: usize = 1000 / 60;
+
+/// Characters for each combination of the two bitplanes, as in `--headless`.
+const ASCII: [char; 4] = ['.', '#', 'o', '@'];
+
+/// Runs `rom` with `keys` held, stopping early if it exits.
+fn run(rom: &[u8], quirks: Quirks, mode: Mode, cycles: usize, keys: &[usize]) -> Cpu {
+    let presses: Vec<_> = keys.iter().map(|&key| (0, key, true)).collect();
+    run_with_input(rom, quirks, mode, cycles, &presses)
+}
+
+/// Runs `rom`, pressing (`true`) or releasing each key of `input` when the
+/// given number of instructions have run, stopping early if it exits.
+fn run_with_input(
+    rom: &[u8],
+    quirks: Quirks,
+    mode: Mode,
+    cycles: usize,
+    input: &[(usize, usize, bool)],
+) -> Cpu {
+    let mut cpu = Cpu::from_rom(rom, quirks, mode).unwrap();
+    cpu.set_rng(Rng::new(0));
+
+    for cycle in 1..=cycles {
+        if cpu.has_exited() {
+            break;
+        }
+        for &(_, key, pressed) in input.iter().filter(|(at, ..)| *at == cycle - 1) {
+            cpu.keys[key] = pressed;
+        }
+        if let Err(err) = cpu.tick() {
+            panic!("{}\n{}", err, cpu.dump_registers());
+        }
+        if cycle % CYCLES_PER_FRAME == 0 {
+            cpu.decrement_timers();
+        }
+    }
+    cpu
+}
+
+fn render(cpu: &Cpu) -> String {
+    let mut text = String::new();
+    for row in cpu.pixels[..(cpu.width() * cpu.height()) as usize].chunks(cpu.width() as usize) {
+        text.extend(row.iter().map(|pixel| ASCII[*pixel as usize & 0b11]));
+        text.push('\n');
+    }
+    text
+}
+
+fn check(name: &str, cpu: &Cpu) {
+    let path = Path::new("tests/golden").join(format!("{}.txt", name));
+    let actual = render(cpu);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "no golden image at {}, check that this screen is right and run \
+             with UPDATE_GOLDEN=1 to record it:\n{}",
+            path.display(),
+            actual
+        )
+    });
+    assert!(
+        actual == expected,
+        "{} doesn't match {}, got:\n{}",
+        name,
+        path.display(),
+        actual
+    );
+}
+
+fn octo(name: &str, mode: Mode) -> Vec<u8> {
+    let path = format!("tests/roms/{}.8o", name);
+    let source = fs::read_to_string(&path).unwrap();
+    compile(&source, mode)
+        .unwrap_or_else(|err| panic!("{}: {}", path, err))
+        .rom
+}
+
+/// The Timendus ROM called `name`, or `None` if it hasn't been copied in.
+fn timendus(name: &str) -> Option<Vec<u8>> {
+    let path: PathBuf = ["tests", "roms", "timendus", &format!("{}.ch8", name)]
+        .iter()
+        .collect();
+    if !path.exists() {
+        eprintln!("skipping, {} not found", path.display());
+        return None;
+    }
+    Some(fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err)))
+}
+
+#[test]
+fn ibm_logo() {
+    let rom = fs::read("ibm_logo.ch8").unwrap();
+    check(
+        "ibm_logo",
+        &run(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 1000, &[]),
+    );
+}
+
+#[test]
+fn opcodes_vip() {
+    let rom = octo("opcodes", Mode::Chip8);
+    let cpu = run(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 20_000, &[5]);
+    check("opcodes_vip", &cpu);
+}
+
+#[test]
+fn opcodes_schip() {
+    let rom = octo("opcodes", Mode::Chip8);
+    let cpu = run(&rom, Quirks::SUPER_CHIP, Mode::Chip8, 20_000, &[5]);
+    check("opcodes_schip", &cpu);
+}
+
+#[test]
+fn opcodes_modern() {
+    let rom = octo("opcodes", Mode::Chip8);
+    let cpu = run(&rom, Quirks::MODERN, Mode::Chip8, 20_000, &[5]);
+    check("opcodes_modern", &cpu);
+}
+
+#[test]
+fn schip() {
+    let rom = octo("schip", Mode::Chip8);
+    let cpu = run(&rom, Quirks::SUPER_CHIP, Mode::Chip8, 20_000, &[]);
+    assert!(cpu.has_exited());
+    check("schip", &cpu);
+}
+
+#[test]
+fn xo_chip() {
+    let rom = octo("xo", Mode::XoChip);
+    let cpu = run(&rom, Quirks::MODERN, Mode::XoChip, 20_000, &[]);
+    assert!(cpu.has_exited());
+    assert!(cpu.audio_pattern().is_some());
+    check("xo_chip", &cpu);
+}
+
+#[test]
+fn timendus_chip8_logo() {
+    let Some(rom) = timendus("1-chip8-logo") else {
+        return;
+    };
+    check(
+        "timendus_chip8_logo",
+        &run(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 1000, &[]),
+    );
+}
+
+#[test]
+fn timendus_ibm_logo() {
+    let Some(rom) = timendus("2-ibm-logo") else {
+        return;
+    };
+    check(
+        "timendus_ibm_logo",
+        &run(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 1000, &[]),
+    );
+}
+
+#[test]
+fn timendus_corax() {
+    let Some(rom) = timendus("3-corax+") else {
+        return;
+    };
+    check(
+        "timendus_corax",
+        &run(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 10_000, &[]),
+    );
+}
+
+#[test]
+fn timendus_flags() {
+    let Some(rom) = timendus("4-flags") else {
+        return;
+    };
+    check(
+        "timendus_flags",
+        &run(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 10_000, &[]),
+    );
+}
+
+#[test]
+fn timendus_quirks() {
+    let Some(rom) = timendus("5-quirks") else {
+        return;
+    };
+    // key 1 picks the CHIP-8 platform from the menu
+    let input = [(1_000, 0x1, true), (2_000, 0x1, false)];
+    let cpu = run_with_input(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 100_000, &input);
+    check("timendus_quirks", &cpu);
+}
+
+#[test]
+fn timendus_keypad() {
+    let Some(rom) = timendus("6-keypad") else {
+        return;
+    };
+    // key 3 picks the FX0A test from the menu, which then waits for a key
+    // to be pressed and released
+    let input = [
+        (1_000, 0x3, true),
+        (2_000, 0x3, false),
+        (4_000, 0xA, true),
+        (6_000, 0xA, false),
+    ];
+    let cpu = run_with_input(&rom, Quirks::COSMAC_VIP, Mode::Chip8, 10_000, &input);
+    check("timendus_keypad", &cpu);
+}
@@ -0,0 +1,121 @@
+//! Records movies through the library and replays them.
+
+use chip8::{compile, Cpu, Frame, Mode, Movie, MovieError, Quirks, Rng};
+
+/// Waits for a key, then shows a random byte at a position that depends on
+/// the key, so the screen depends on both the input and the seed.
+const SOURCE: &str = "
+: main
+  loop
+    v0 := key
+    v1 := random 0xFF
+    i := scratch
+    save v1
+    i := scratch
+    clear
+    sprite v0 v0 2
+  again
+: scratch
+  0 0
+";
+
+const SEED: u64 = 7;
+
+/// Offsets of the settings in a saved movie, after the magic number,
+/// version and ROM hash.
+const MODE: usize = 13;
+const QUIRKS: usize = 14;
+const RNG_KIND: usize = 15;
+
+fn record(rom: &[u8]) -> (Movie, Cpu) {
+    let mut cpu = Cpu::from_rom(rom, Quirks::MODERN, Mode::Chip8).unwrap();
+    cpu.set_rng(Rng::new(SEED));
+    let mut movie = Movie::new(&cpu, SEED);
+
+    for frame in 0..180 {
+        let mut keys = [false; 16];
+        keys[frame % 7] = frame % 3 == 0;
+        let cycles = 10 + frame as u32 % 5;
+
+        cpu.keys = keys;
+        for _ in 0..cycles {
+            cpu.tick().unwrap();
+        }
+        cpu.decrement_timers();
+        movie.push(Frame { keys, cycles }, &cpu);
+    }
+    (movie, cpu)
+}
+
+fn replay(movie: &Movie, rom: &[u8]) -> Result<Cpu, MovieError> {
+    let mut cpu = movie.start(rom)?;
+    for (index, frame) in movie.frames().iter().enumerate() {
+        cpu.keys = frame.keys;
+        for _ in 0..frame.cycles {
+            cpu.tick().unwrap();
+        }
+        cpu.decrement_timers();
+        movie.check(index + 1, &cpu)?;
+    }
+    Ok(cpu)
+}
+
+#[test]
+fn replays_recording() {
+    let rom = compile(SOURCE, Mode::Chip8).unwrap().rom;
+    let (movie, recorded) = record(&rom);
+
+    let loaded = Movie::load(&movie.save()).unwrap();
+    assert_eq!(loaded, movie);
+
+    let replayed = replay(&loaded, &rom).unwrap();
+    assert_eq!(replayed.pixels, recorded.pixels);
+    assert_eq!(replayed.v(), recorded.v());
+}
+
+#[test]
+fn detects_desync() {
+    let rom = compile(SOURCE, Mode::Chip8).unwrap().rom;
+    let (mut movie, _) = record(&rom);
+    movie.seed += 1;
+
+    assert!(matches!(
+        replay(&movie, &rom),
+        Err(MovieError::Desync { frame: 60, .. })
+    ));
+}
+
+#[test]
+fn rejects_other_roms() {
+    let rom = compile(SOURCE, Mode::Chip8).unwrap().rom;
+    let (movie, _) = record(&rom);
+
+    assert!(matches!(
+        movie.start(&[0x12, 0x00]),
+        Err(MovieError::RomMismatch)
+    ));
+    assert!(matches!(
+        Movie::load(&movie.save()[..20]),
+        Err(MovieError::Truncated)
+    ));
+    assert!(matches!(Movie::load(b"C8ST"), Err(MovieError::BadMagic)));
+}
+
+#[test]
+fn rejects_out_of_range_settings() {
+    let rom = compile(SOURCE, Mode::Chip8).unwrap().rom;
+    let (movie, _) = record(&rom);
+
+    for (offset, value, field) in [
+        (MODE, 2, "mode"),
+        (QUIRKS, 0x80, "quirks"),
+        (RNG_KIND, 2, "RNG kind"),
+    ] {
+        let mut data = movie.save();
+        data[offset] = value;
+        match Movie::load(&data) {
+            Err(MovieError::OutOfRange(name)) => assert_eq!(name, field),
+            other => panic!("{}: expected OutOfRange, got {:?}", field, other),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Compiles Octo's structured control flow and checks the errors.
+
+use chip8::{compile, AsmError, Cpu, Mode, Quirks};
+
+fn words(source: &str) -> Vec<u16> {
+    let rom = compile(source, Mode::Chip8)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .rom;
+    rom.chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+fn error(source: &str) -> AsmError {
+    compile(source, Mode::Chip8).expect_err("source should not compile")
+}
+
+#[test]
+fn if_then() {
+    // the next instruction is skipped when the condition doesn't hold
+    assert_eq!(words(": main if v0 == 5 then v1 := 1"), [0x4005, 0x6101]);
+    assert_eq!(words(": main if v0 != v2 then v1 := 1"), [0x5020, 0x6101]);
+    assert_eq!(words(": main if v3 key then v1 := 1"), [0xE3A1, 0x6101]);
+}
+
+#[test]
+fn if_begin_else_end() {
+    assert_eq!(
+        words(": main if v0 == 5 begin v1 := 1 else v1 := 2 end"),
+        [
+            0x3005, // 200: skip the jump to else when v0 == 5
+            0x1208, // 202: jump to else
+            0x6101, // 204
+            0x120A, // 206: jump past else
+            0x6102, // 208
+        ]
+    );
+    assert_eq!(
+        words(": main if v0 == 5 begin v1 := 1 end"),
+        [0x3005, 0x1206, 0x6101]
+    );
+}
+
+#[test]
+fn loop_while_again() {
+    assert_eq!(
+        words(": main loop v0 += 1 while v0 != 10 again"),
+        [
+            0x7001, // 200
+            0x400A, // 202: skip the exit while v0 != 10
+            0x1208, // 204: exit
+            0x1200, // 206: again
+        ]
+    );
+}
+
+#[test]
+fn comparisons_through_vf() {
+    // vf := v0, vf -= v1 leaves vf as the no-borrow flag of v0 - v1
+    assert_eq!(
+        words(": main if v0 < v1 then v2 := 1"),
+        [0x8F00, 0x8F15, 0x4F00, 0x6201]
+    );
+
+    for a in [3u8, 7, 9] {
+        for b in [3u8, 7, 9] {
+            for (op, expected) in [("<", a < b), (">", a > b), ("<=", a <= b), (">=", a >= b)] {
+                let source = format!(
+                    ": main
+                       v0 := {a}
+                       v1 := {b}
+                       if v0 {op} v1 then v2 := 1
+                       if v0 {op} {b} then v3 := 1
+                       loop again",
+                );
+                let rom = compile(&source, Mode::Chip8).unwrap().rom;
+                let mut cpu = Cpu::from_rom(&rom, Quirks::MODERN, Mode::Chip8).unwrap();
+                for _ in 0..20 {
+                    cpu.tick().unwrap();
+                }
+                let name = format!("{} {} {}", a, op, b);
+                assert_eq!(cpu.v()[2] == 1, expected, "{} (registers)", name);
+                assert_eq!(cpu.v()[3] == 1, expected, "{} (constant)", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn reports_unknown_labels() {
+    let err = error(": main\n  v0 := 1\n  jump nowhere\n  i := elsewhere");
+    assert_eq!(err.line, 3);
+    assert_eq!(err.message, "undefined label nowhere");
+
+    let err = error(": main\n  i := elsewhere");
+    assert_eq!(err.line, 2);
+    assert_eq!(err.message, "undefined label elsewhere");
+}
+
+#[test]
+fn reports_bad_registers() {
+    let err = error(": main\n  v0 := 1\n  vz := 1");
+    assert_eq!(err.line, 3);
+    assert_eq!(err.message, "expected a register but found vz");
+
+    let err = error(": main\n\n  sprite v0 vx 1");
+    assert_eq!(err.line, 3);
+    assert_eq!(err.message, "expected a register but found vx");
+
+    let err = error(":alias speed v16\n: main");
+    assert_eq!(err.line, 1);
+    assert_eq!(err.message, "expected a register but found v16");
+}
+
+#[test]
+fn reports_unbalanced_blocks() {
+    assert_eq!(error(": main\n  end").message, "end without if ... begin");
+    assert_eq!(error(": main\n  again").message, "again without loop");
+    assert_eq!(
+        error(": main\n  while v0 == 1").message,
+        "while outside of a loop"
+    );
+}
+
+#[test]
+fn reports_recursive_macros() {
+    let err = error(": main\n  v0 := 1\n:macro m { m }\n  m");
+    assert_eq!(err.line, 4);
+    assert_eq!(err.message, "macro m expands recursively");
+
+    let err = error(":macro a { b }\n:macro b { a }\n: main\n  a");
+    assert_eq!(err.line, 4);
+    assert_eq!(err.message, "macro a expands recursively");
+
+    // macros using other macros are fine
+    let source = ":macro one r { r := 1 }\n:macro two r { one r r += 1 }\n: main\n  two v3";
+    assert_eq!(words(source), [0x6301, 0x7301]);
+}
@@ -0,0 +1,379 @@
+//! Runs single instructions on a `Cpu` built with known registers and checks
+//! their effects.
+
+use chip8::{Cpu, CpuBuilder, CpuError, Mode, Quirks, Rng, FONT_ADDR};
+
+/// Builds a CPU for `rom` with the modern quirks, which don't wait for the
+/// vertical blank to draw.
+fn modern(rom: &[u8]) -> CpuBuilder<'_> {
+    Cpu::builder(rom).quirks(Quirks::MODERN)
+}
+
+fn step(builder: CpuBuilder) -> Cpu {
+    let mut cpu = builder.build().unwrap();
+    cpu.tick().unwrap();
+    cpu
+}
+
+#[test]
+fn builder_defaults() {
+    let cpu = Cpu::builder(&[0x12, 0x34]).build().unwrap();
+    assert_eq!(cpu.pc(), 0x200);
+    assert_eq!(cpu.i(), 0);
+    assert_eq!(cpu.v(), &[0; 16]);
+    assert!(cpu.stack().is_empty());
+    assert_eq!(cpu.quirks(), Quirks::default());
+    assert_eq!(cpu.mode(), Mode::Chip8);
+    assert_eq!(&cpu.memory()[0x200..0x202], &[0x12, 0x34]);
+    assert_eq!(cpu.memory()[FONT_ADDR], 0xF0);
+}
+
+#[test]
+fn builder_rejects_bad_state() {
+    assert!(matches!(
+        Cpu::builder(&[]).pc(0x1000).build(),
+        Err(CpuError::MemoryOutOfBounds { addr: 0x1000 })
+    ));
+    assert!(matches!(
+        Cpu::builder(&[]).stack(&[0x200; 17]).build(),
+        Err(CpuError::StackOverflow)
+    ));
+    assert!(matches!(
+        Cpu::builder(&[0; 0xE01]).build(),
+        Err(CpuError::RomTooLarge { .. })
+    ));
+}
+
+#[test]
+fn clear_screen() {
+    let mut cpu = modern(&[0x00, 0xE0]).build().unwrap();
+    cpu.pixels[0] = 1;
+    cpu.tick().unwrap();
+    assert!(cpu.pixels.iter().all(|&pixel| pixel == 0));
+}
+
+#[test]
+fn call_and_return() {
+    let cpu = step(modern(&[0x23, 0x00]));
+    assert_eq!(cpu.pc(), 0x300);
+    assert_eq!(cpu.stack(), &[0x202]);
+
+    let cpu = step(modern(&[0x00, 0xEE]).stack(&[0x204, 0x206]));
+    assert_eq!(cpu.pc(), 0x206);
+    assert_eq!(cpu.stack(), &[0x204]);
+}
+
+#[test]
+fn stack_faults() {
+    let mut cpu = modern(&[0x00, 0xEE]).build().unwrap();
+    assert!(matches!(cpu.tick(), Err(CpuError::StackUnderflow)));
+    assert_eq!(cpu.pc(), 0x200);
+
+    let mut cpu = modern(&[0x22, 0x00]).stack(&[0x200; 16]).build().unwrap();
+    assert!(matches!(cpu.tick(), Err(CpuError::StackOverflow)));
+}
+
+#[test]
+fn jump() {
+    assert_eq!(step(modern(&[0x1A, 0xBC])).pc(), 0xABC);
+}
+
+#[test]
+fn jump_with_offset() {
+    let rom = [0xB3, 0x00];
+    let vip = step(modern(&rom).v(0, 0x10).v(3, 0x20));
+    assert_eq!(vip.pc(), 0x310);
+    let chip48 = step(modern(&rom).quirks(Quirks::CHIP_48).v(0, 0x10).v(3, 0x20));
+    assert_eq!(chip48.pc(), 0x320);
+}
+
+#[test]
+fn skips() {
+    // (instruction, skips)
+    let cases: [([u8; 2], bool); 8] = [
+        ([0x31, 0x07], true),
+        ([0x31, 0x08], false),
+        ([0x41, 0x07], false),
+        ([0x41, 0x08], true),
+        ([0x51, 0x20], true),
+        ([0x51, 0x30], false),
+        ([0x91, 0x20], false),
+        ([0x91, 0x30], true),
+    ];
+    for (rom, skips) in cases {
+        let cpu = step(modern(&rom).v(1, 7).v(2, 7).v(3, 8));
+        let expected = if skips { 0x204 } else { 0x202 };
+        assert_eq!(cpu.pc(), expected, "{:02X}{:02X}", rom[0], rom[1]);
+    }
+}
+
+#[test]
+fn load_and_add_immediate() {
+    assert_eq!(step(modern(&[0x65, 0x42])).v()[5], 0x42);
+
+    let cpu = step(modern(&[0x75, 0x03]).v(5, 0xFE).v(0xF, 7));
+    assert_eq!(cpu.v()[5], 0x01);
+    assert_eq!(cpu.v()[0xF], 7);
+}
+
+#[test]
+fn logic() {
+    // (instruction, result)
+    let cases: [([u8; 2], u8); 4] = [
+        ([0x80, 0x10], 0x3C),
+        ([0x80, 0x11], 0xFF),
+        ([0x80, 0x12], 0x0C),
+        ([0x80, 0x13], 0xF3),
+    ];
+    for (rom, result) in cases {
+        let cpu = step(modern(&rom).v(0, 0xCF).v(1, 0x3C).v(0xF, 5));
+        assert_eq!(cpu.v()[0], result, "{:02X}{:02X}", rom[0], rom[1]);
+        assert_eq!(cpu.v()[0xF], 5);
+    }
+
+    let vip = step(Cpu::builder(&[0x80, 0x11]).v(0xF, 5));
+    assert_eq!(vip.v()[0xF], 0);
+}
+
+#[test]
+fn arithmetic_flags() {
+    // (instruction, vx, vy, result, vf)
+    let cases: [([u8; 2], u8, u8, u8, u8); 6] = [
+        ([0x80, 0x14], 0xF0, 0x20, 0x10, 1),
+        ([0x80, 0x14], 0x10, 0x20, 0x30, 0),
+        ([0x80, 0x15], 0x10, 0x20, 0xF0, 0),
+        ([0x80, 0x15], 0x20, 0x20, 0x00, 1),
+        ([0x80, 0x17], 0x10, 0x30, 0x20, 1),
+        ([0x80, 0x17], 0x30, 0x10, 0xE0, 0),
+    ];
+    for (rom, vx, vy, result, vf) in cases {
+        let cpu = step(modern(&rom).v(0, vx).v(1, vy));
+        let name = format!("{:02X}{:02X} {:02X} {:02X}", rom[0], rom[1], vx, vy);
+        assert_eq!(cpu.v()[0], result, "{}", name);
+        assert_eq!(cpu.v()[0xF], vf, "{}", name);
+    }
+
+    // the flag wins when vx is vf
+    let cpu = step(modern(&[0x8F, 0x15]).v(0xF, 0x30).v(1, 0x10));
+    assert_eq!(cpu.v()[0xF], 1);
+}
+
+#[test]
+fn shifts() {
+    let vip = step(modern(&[0x80, 0x16]).v(0, 0xFF).v(1, 0x05));
+    assert_eq!((vip.v()[0], vip.v()[0xF]), (0x02, 1));
+    let vip = step(modern(&[0x80, 0x1E]).v(0, 0xFF).v(1, 0x81));
+    assert_eq!((vip.v()[0], vip.v()[0xF]), (0x02, 1));
+
+    let chip48 = step(
+        modern(&[0x80, 0x16])
+            .quirks(Quirks::CHIP_48)
+            .v(0, 0x04)
+            .v(1, 0x05),
+    );
+    assert_eq!((chip48.v()[0], chip48.v()[0xF]), (0x02, 0));
+}
+
+#[test]
+fn index() {
+    assert_eq!(step(modern(&[0xA1, 0x23])).i(), 0x123);
+    assert_eq!(step(modern(&[0xF3, 0x1E]).i(0x100).v(3, 0x10)).i(), 0x110);
+    let cpu = step(modern(&[0xF3, 0x29]).v(3, 0x0A));
+    assert_eq!(cpu.i() as usize, FONT_ADDR + 0x0A * 5);
+}
+
+#[test]
+fn random() {
+    let masked = step(modern(&[0xC0, 0x0F]).rng(Rng::new(1)).v(0, 0xFF));
+    assert_eq!(masked.v()[0] & 0xF0, 0);
+    assert_eq!(step(modern(&[0xC0, 0x00]).v(0, 0xFF)).v()[0], 0);
+}
+
+#[test]
+fn draw() {
+    // draws the 0 glyph at (2, 1), then again to erase it
+    let rom = [0xD0, 0x15, 0xD0, 0x15];
+    let mut cpu = modern(&rom)
+        .i(FONT_ADDR as u16)
+        .v(0, 2)
+        .v(1, 1)
+        .build()
+        .unwrap();
+    cpu.tick().unwrap();
+    let row: Vec<u8> = (0..8).map(|x| cpu.pixels[64 + x]).collect();
+    assert_eq!(row, [0, 0, 1, 1, 1, 1, 0, 0]);
+    assert_eq!(cpu.v()[0xF], 0);
+
+    cpu.tick().unwrap();
+    assert!(cpu.pixels.iter().all(|&pixel| pixel == 0));
+    assert_eq!(cpu.v()[0xF], 1);
+}
+
+#[test]
+fn draw_waits_for_vblank() {
+    let mut cpu = Cpu::builder(&[0xD0, 0x15]).build().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x200);
+    cpu.decrement_timers();
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn keys() {
+    let mut cpu = modern(&[0xE3, 0x9E]).v(3, 5).build().unwrap();
+    cpu.keys[5] = true;
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x204);
+
+    let cpu = step(modern(&[0xE3, 0xA1]).v(3, 5));
+    assert_eq!(cpu.pc(), 0x204);
+}
+
+#[test]
+fn wait_for_key() {
+    let mut cpu = modern(&[0xF3, 0x0A]).build().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x200);
+
+    cpu.keys[0xB] = true;
+    cpu.tick().unwrap();
+    assert_eq!(cpu.pc(), 0x202);
+    assert_eq!(cpu.v()[3], 0xB);
+}
+
+#[test]
+fn timers() {
+    let cpu = step(modern(&[0xF3, 0x07]).delay_timer(0x30));
+    assert_eq!(cpu.v()[3], 0x30);
+    let cpu = step(modern(&[0xF3, 0x15]).v(3, 0x20));
+    assert_eq!(cpu.delay_timer(), 0x20);
+    let cpu = step(modern(&[0xF3, 0x18]).v(3, 0x20));
+    assert_eq!(cpu.sound_timer, 0x20);
+}
+
+#[test]
+fn bcd() {
+    let cpu = step(modern(&[0xF3, 0x33]).v(3, 234).i(0x300));
+    assert_eq!(&cpu.memory()[0x300..0x303], &[2, 3, 4]);
+    assert_eq!(cpu.i(), 0x300);
+}
+
+#[test]
+fn store_and_load() {
+    let cpu = step(modern(&[0xF2, 0x55]).registers([1; 16]).v(2, 3).i(0x300));
+    assert_eq!(&cpu.memory()[0x300..0x304], &[1, 1, 3, 0]);
+    assert_eq!(cpu.i(), 0x303);
+
+    let chip48 = step(modern(&[0xF2, 0x55]).quirks(Quirks::CHIP_48).i(0x300));
+    assert_eq!(chip48.i(), 0x300);
+
+    let cpu = step(modern(&[0xF1, 0x65, 0xAB, 0xCD]).i(0x202));
+    assert_eq!(&cpu.v()[..3], &[0xAB, 0xCD, 0]);
+}
+
+#[test]
+fn memory_faults() {
+    let mut cpu = modern(&[0xF1, 0x65]).i(0xFFF).build().unwrap();
+    assert!(matches!(
+        cpu.tick(),
+        Err(CpuError::MemoryOutOfBounds { .. })
+    ));
+    assert_eq!(cpu.pc(), 0x200);
+}
+
+#[test]
+fn unknown_opcode() {
+    let mut cpu = modern(&[0x80, 0x18]).build().unwrap();
+    assert!(matches!(
+        cpu.tick(),
+        Err(CpuError::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x8018
+        })
+    ));
+
+    // only 00E0 clears the screen, as the disassembler expects
+    let mut cpu = modern(&[0x01, 0xE0]).build().unwrap();
+    assert!(matches!(
+        cpu.tick(),
+        Err(CpuError::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x01E0
+        })
+    ));
+}
+
+#[test]
+fn super_chip() {
+    let cpu = step(modern(&[0x00, 0xFF]));
+    assert!(cpu.is_hires());
+    assert_eq!(cpu.width(), 128);
+
+    let cpu = step(modern(&[0x00, 0xFD]));
+    assert!(cpu.has_exited());
+
+    let mut cpu = modern(&[0xF1, 0x75, 0xF1, 0x85])
+        .v(0, 0xA)
+        .v(1, 0xB)
+        .build()
+        .unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(&cpu.rpl_flags()[..2], &[0xA, 0xB]);
+}
+
+#[test]
+fn resolution_switch() {
+    let rom = [0x00, 0xFF, 0x00, 0xFE];
+    let lit = |cpu: &Cpu| -> Vec<usize> {
+        let area = (cpu.width() * cpu.height()) as usize;
+        (0..area).filter(|&at| cpu.pixels[at] != 0).collect()
+    };
+
+    // SUPER-CHIP keeps the picture, scaled to the new resolution
+    let mut cpu = Cpu::builder(&rom)
+        .quirks(Quirks::SUPER_CHIP)
+        .build()
+        .unwrap();
+    cpu.pixels[64 + 1] = 1;
+    cpu.tick().unwrap();
+    assert_eq!(
+        lit(&cpu),
+        [2 * 128 + 2, 2 * 128 + 3, 3 * 128 + 2, 3 * 128 + 3]
+    );
+    cpu.tick().unwrap();
+    assert_eq!(lit(&cpu), [64 + 1]);
+
+    let mut cpu = modern(&rom).build().unwrap();
+    cpu.pixels[64 + 1] = 1;
+    cpu.tick().unwrap();
+    assert!(lit(&cpu).is_empty());
+}
+
+#[test]
+fn xo_chip() {
+    let rom = [0xF0, 0x00, 0x12, 0x34];
+    let cpu = step(modern(&rom).mode(Mode::XoChip));
+    assert_eq!((cpu.i(), cpu.pc()), (0x1234, 0x204));
+
+    let cpu = step(modern(&[0xF3, 0x01]).mode(Mode::XoChip));
+    assert_eq!(cpu.planes(), 3);
+
+    let cpu = step(modern(&[0xF2, 0x3A]).mode(Mode::XoChip).v(2, 80));
+    assert_eq!(cpu.pitch(), 80);
+
+    // 5XY2 stores a range of registers without moving I
+    let cpu = step(
+        modern(&[0x51, 0x32])
+            .mode(Mode::XoChip)
+            .v(1, 1)
+            .v(2, 2)
+            .v(3, 3)
+            .i(0x300),
+    );
+    assert_eq!(&cpu.memory()[0x300..0x303], &[1, 2, 3]);
+    assert_eq!(cpu.i(), 0x300);
+
+    let mut chip8 = modern(&[0xF0, 0x00]).build().unwrap();
+    assert!(chip8.tick().is_err());
+}
@@ -0,0 +1,68 @@
+//! Rewinds through the snapshots of a running program.
+
+use chip8::{compile, Cpu, Mode, Quirks, RewindBuffer};
+
+/// Counts up in memory and draws the count, so every frame changes the
+/// registers, memory and screen a little.
+const SOURCE: &str = "
+: main
+  i := counter
+  loop
+    load v0
+    v0 += 1
+    i := counter
+    save v0
+    v1 := v0
+    v2 := v0
+    i := counter
+    sprite v1 v2 1
+    i := counter
+  again
+: counter
+  0
+";
+
+fn run(cpu: &mut Cpu, cycles: u32) {
+    for _ in 0..cycles {
+        cpu.tick().unwrap();
+    }
+    cpu.decrement_timers();
+}
+
+#[test]
+fn rewinds_to_each_frame() {
+    for mode in [Mode::Chip8, Mode::XoChip] {
+        let rom = compile(SOURCE, mode).unwrap().rom;
+        let mut cpu = Cpu::from_rom(&rom, Quirks::MODERN, mode).unwrap();
+        let mut rewind = RewindBuffer::new(100);
+        let mut states = vec![];
+
+        for _ in 0..300 {
+            rewind.push(&cpu);
+            states.push(cpu.save_state());
+            run(&mut cpu, 15);
+        }
+        assert_eq!(rewind.len(), 100);
+        // the full state once, then a few bytes a frame
+        assert!(rewind.size() < cpu.save_state().len() + 100 * 256);
+
+        for state in states.iter().rev().take(100) {
+            assert!(rewind.rewind(&mut cpu).unwrap());
+            assert_eq!(&cpu.save_state(), state);
+        }
+        assert!(!rewind.rewind(&mut cpu).unwrap());
+        assert!(rewind.is_empty());
+    }
+}
+
+#[test]
+fn reports_failed_rewinds() {
+    let rom = compile(SOURCE, Mode::Chip8).unwrap().rom;
+    let cpu = Cpu::from_rom(&rom, Quirks::MODERN, Mode::Chip8).unwrap();
+    let mut rewind = RewindBuffer::new(10);
+    rewind.push(&cpu);
+
+    // snapshots only load into the ROM they were taken from
+    let mut other = Cpu::from_rom(&[0x12, 0x00], Quirks::MODERN, Mode::Chip8).unwrap();
+    assert!(rewind.rewind(&mut other).is_err());
+}
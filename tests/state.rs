@@ -0,0 +1,87 @@
+//! Save states round trip, and corrupt ones are rejected.
+
+use chip8::{Cpu, Mode, Quirks, Rng, StateError};
+
+/// Offsets into a CHIP-8 state with an empty stack.
+const STACK_DEPTH: usize = 4 + 1 + 8 + 4 + 4096 + 16 + 2 + 4;
+const PLANES: usize = STACK_DEPTH + 1 + 2 + 1;
+const RNG_KIND: usize = PLANES + 1 + 8192 + 16 + 1 + 16 + 1 + 16 + 1;
+
+fn cpu() -> Cpu {
+    let mut cpu = Cpu::from_rom(&[0x12, 0x00], Quirks::MODERN, Mode::Chip8).unwrap();
+    cpu.set_rng(Rng::new(1));
+    cpu
+}
+
+fn load(state: &[u8]) -> Result<(), StateError> {
+    cpu().load_state(state)
+}
+
+#[test]
+fn round_trips() {
+    let mut cpu = Cpu::builder(&[0x12, 0x00])
+        .v(3, 0x42)
+        .i(0x300)
+        .stack(&[0x202, 0x204])
+        .delay_timer(9)
+        .build()
+        .unwrap();
+    cpu.keys[5] = true;
+    let state = cpu.save_state();
+
+    let mut loaded = Cpu::from_rom(&[0x12, 0x00], Quirks::default(), Mode::Chip8).unwrap();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.save_state(), state);
+    assert_eq!(loaded.v()[3], 0x42);
+    assert_eq!(loaded.stack(), &[0x202, 0x204]);
+}
+
+#[test]
+fn rejects_out_of_range_fields() {
+    let state = cpu().save_state();
+    assert_eq!(state.len(), RNG_KIND + 1 + 8);
+    assert!(load(&state).is_ok());
+
+    let corrupt = |at: usize, value: u8| {
+        let mut state = state.clone();
+        state[at] = value;
+        load(&state)
+    };
+    assert!(matches!(
+        corrupt(STACK_DEPTH, 17),
+        Err(StateError::OutOfRange("stack depth"))
+    ));
+    assert!(matches!(
+        corrupt(PLANES, 0b111),
+        Err(StateError::OutOfRange("plane mask"))
+    ));
+    assert!(matches!(
+        corrupt(PLANES + 1, 4),
+        Err(StateError::OutOfRange("pixel"))
+    ));
+    assert!(matches!(
+        corrupt(RNG_KIND, 2),
+        Err(StateError::OutOfRange("RNG kind"))
+    ));
+    assert!(matches!(
+        corrupt(STACK_DEPTH - 4, 0xFF),
+        Err(StateError::OutOfRange("program counter"))
+    ));
+
+    let mut zero_rng = state.clone();
+    zero_rng[RNG_KIND + 1..].fill(0);
+    assert!(matches!(
+        load(&zero_rng),
+        Err(StateError::OutOfRange("RNG state"))
+    ));
+}
+
+#[test]
+fn rejects_other_roms() {
+    let state = cpu().save_state();
+    let mut other = Cpu::from_rom(&[0x13, 0x00], Quirks::MODERN, Mode::Chip8).unwrap();
+    assert!(matches!(
+        other.load_state(&state),
+        Err(StateError::RomMismatch)
+    ));
+}
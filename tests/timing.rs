@@ -0,0 +1,80 @@
+//! Checks the COSMAC VIP cycle costs and how frames are filled with them.
+
+use chip8::{vip_cycles, Cpu, FrameClock, Quirks, Timing, VIP_FRAME_CYCLES, VIP_INTERRUPT_CYCLES};
+
+fn cost(rom: &[u8], v: &[(usize, u8)]) -> u32 {
+    let mut builder = Cpu::builder(rom);
+    for &(reg, value) in v {
+        builder = builder.v(reg, value);
+    }
+    vip_cycles(&builder.build().unwrap())
+}
+
+#[test]
+fn costs_depend_on_operands() {
+    // BCD counts each digit down
+    assert!(cost(&[0xF0, 0x33], &[(0, 199)]) > cost(&[0xF0, 0x33], &[(0, 100)]));
+    // loads and stores loop over the registers
+    assert!(cost(&[0xFF, 0x65], &[]) > cost(&[0xF0, 0x65], &[]));
+    // sprites are shifted into place unless X is a multiple of 8
+    assert!(cost(&[0xD0, 0x15], &[(0, 3)]) > cost(&[0xD0, 0x15], &[(0, 8)]));
+    assert!(cost(&[0xD0, 0x1F], &[]) > cost(&[0xD0, 0x11], &[]));
+    // skips cost more when they skip
+    assert!(cost(&[0x30, 0x00], &[]) > cost(&[0x30, 0x01], &[]));
+    assert!(cost(&[0x00, 0xE0], &[]) > cost(&[0x60, 0x00], &[]));
+}
+
+#[test]
+fn instructions_per_frame() {
+    let cpu = Cpu::builder(&[0x12, 0x00]).build().unwrap();
+    let mut clock = FrameClock::new(Timing::Instructions(3));
+    for _ in 0..3 {
+        assert!(!clock.is_done());
+        clock.charge(&cpu);
+    }
+    assert!(clock.is_done());
+    clock.next_frame();
+    assert!(!clock.is_done());
+}
+
+/// Counts the instructions run in each of the first `frames` frames.
+fn run_frames(rom: &[u8], quirks: Quirks, frames: usize) -> Vec<u32> {
+    let mut cpu = Cpu::builder(rom).quirks(quirks).build().unwrap();
+    let mut clock = FrameClock::new(Timing::CosmacVip);
+    let mut counts = vec![];
+    for _ in 0..frames {
+        let mut count = 0;
+        while !clock.is_done() {
+            clock.charge(&cpu);
+            cpu.tick().unwrap();
+            count += 1;
+        }
+        cpu.decrement_timers();
+        clock.next_frame();
+        counts.push(count);
+    }
+    counts
+}
+
+#[test]
+fn fills_frames_with_cycles() {
+    // 6000 then 1200: both cheap, so many run each frame
+    let counts = run_frames(&[0x60, 0x00, 0x12, 0x00], Quirks::MODERN, 3);
+    let pair = vip_cycles(&Cpu::builder(&[0x60, 0x00]).build().unwrap())
+        + vip_cycles(&Cpu::builder(&[0x12, 0x00]).build().unwrap());
+    let budget = VIP_FRAME_CYCLES - VIP_INTERRUPT_CYCLES;
+    assert!(counts[0] >= 2 * (budget / pair));
+    assert!(counts[0] <= 2 * (budget / pair) + 2);
+}
+
+#[test]
+fn display_wait_ends_the_frame() {
+    // D015 then 1200: each frame draws, jumps back and then waits at the
+    // D015 for the next one, and the first frame only waits
+    let rom = [0xD0, 0x15, 0x12, 0x00];
+    assert_eq!(run_frames(&rom, Quirks::COSMAC_VIP, 3), [1, 3, 3]);
+
+    // without the wait the same loop fills each frame
+    let counts = run_frames(&rom, Quirks::MODERN, 3);
+    assert!(counts.iter().all(|&count| count > 2));
+}
@@ -0,0 +1,40 @@
+//! Parses watchpoints as given to `--watch`.
+
+use chip8::{Register, Watchpoint};
+
+#[test]
+fn parses_registers() {
+    assert_eq!(
+        "vf=1".parse(),
+        Ok(Watchpoint::Register {
+            reg: Register::V(0xF),
+            value: Some(1)
+        })
+    );
+    assert_eq!(
+        "I=FFFF".parse(),
+        Ok(Watchpoint::Register {
+            reg: Register::I,
+            value: Some(0xFFFF)
+        })
+    );
+    assert_eq!(
+        "I".parse(),
+        Ok(Watchpoint::Register {
+            reg: Register::I,
+            value: None
+        })
+    );
+}
+
+#[test]
+fn rejects_values_that_dont_fit() {
+    assert_eq!(
+        "V0=1FF".parse::<Watchpoint>(),
+        Err("1FF is out of range for V0".to_string())
+    );
+    assert_eq!(
+        "I=10000".parse::<Watchpoint>(),
+        Err("10000 is out of range for I".to_string())
+    );
+}